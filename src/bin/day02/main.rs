@@ -1,23 +1,23 @@
 use std::env;
+use std::ops::Range;
 use std::str::FromStr;
-
-mod intcode;
-
-use intcode::IntcodeCPU;
-
-fn load_initial_program_state(input: &str) -> Vec<u32> {
-    input
-        .split(',')
-        .enumerate()
-        .map(|(i, pos)| {
-            u32::from_str(pos.trim()).unwrap_or_else(|_| {
-                panic!("Could not interpret '{}' at position {} as u32", pos, i)
-            })
-        })
-        .collect()
+use std::thread;
+
+use aoc2019::intcode::io::QueueIO;
+use aoc2019::intcode::loader;
+use aoc2019::intcode::search::{Search, SearchSpace};
+use aoc2019::intcode::symbolic::{SymbolicCPU, SymbolicError};
+use aoc2019::intcode::taint::describe;
+use aoc2019::intcode::{CPUState, IntcodeCPU};
+
+fn load_initial_program_state(input: &str) -> Vec<i32> {
+    loader::parse(input).unwrap_or_else(|e| {
+        eprintln!("ERROR: Could not load program: {}", e);
+        std::process::exit(1);
+    })
 }
 
-fn set_inputs(state: &mut [u32], noun: u32, verb: u32) {
+fn set_inputs(state: &mut [i32], noun: i32, verb: i32) {
     state[1] = noun;
     state[2] = verb;
 }
@@ -32,32 +32,73 @@ fn part1(input: &str) {
     println!("Value at position 0: {}", cpu.output());
 }
 
-const PART2_NOUN_MIN: u32 = 0;
-const PART2_NOUN_MAX: u32 = 100;
-const PART2_VERB_MIN: u32 = 0;
-const PART2_VERB_MAX: u32 = 100;
-const PART2_TARGET_OUTPUT: u32 = 19690720;
-
-fn part2(input: &str) {
+fn taint(input: &str) {
     let mut program = load_initial_program_state(input);
+    set_inputs(&mut program, 12, 2);
+
+    let mut cpu = IntcodeCPU::with_io(program, QueueIO::default());
+    cpu.enable_taint_tracking(&[1, 2]);
+    cpu.run().expect("Should not have excepted at runtime");
 
-    let program_ref = &program;
+    let taint = cpu.taint().expect("Taint tracking was enabled");
 
-    // Search input space
-    for noun in PART2_NOUN_MIN..PART2_NOUN_MAX {
-        for verb in PART2_VERB_MIN..PART2_VERB_MAX {
-            let mut program = program_ref.clone();
-            set_inputs(&mut program, noun, verb);
+    println!(
+        "Position 0 depends on: {}",
+        describe(taint.cell(0).expect("Output (pos 0) not found in program"))
+    );
+    for (pos, sources) in taint.tainted_cells() {
+        println!("{:>6}: {}", pos, describe(sources));
+    }
+}
 
-            let mut cpu = IntcodeCPU::new(program);
-            let res = cpu.run();
+const PART2_NOUN_MIN: i32 = 0;
+const PART2_NOUN_MAX: i32 = 100;
+const PART2_VERB_MIN: i32 = 0;
+const PART2_VERB_MAX: i32 = 100;
+const PART2_TARGET_OUTPUT: i32 = 19690720;
+const PART2_STEP_LIMIT: u64 = 100_000;
+
+/// Treats noun and verb as variables, so that for programs where position 0
+/// ends up linear in them the answer can be solved for without a search.
+/// Solutions are worked out in `i64`, so each still needs checking with
+/// `produces_target`.
+fn solve_part2_symbolically(program: &[i32]) -> Result<Vec<(i32, i32)>, SymbolicError> {
+    let mut cpu = SymbolicCPU::new(program, &[1, 2]);
+    cpu.run(PART2_STEP_LIMIT)?;
+
+    let ranges = [
+        (1, i64::from(PART2_NOUN_MIN)..i64::from(PART2_NOUN_MAX)),
+        (2, i64::from(PART2_VERB_MIN)..i64::from(PART2_VERB_MAX)),
+    ];
+    let solutions = cpu.solve(0, i64::from(PART2_TARGET_OUTPUT), &ranges)?;
+
+    Ok(solutions
+        .iter()
+        .map(|vars| (vars[&1] as i32, vars[&2] as i32))
+        .collect())
+}
 
-            if let Err(ex) = res {
-                eprintln!("WARNING: CPU exception {:?} at position {} while running with inputs (noun = {}, verb = {}). Skipping", ex, cpu.pc(), noun, verb);
-                continue;
-            }
+/// Runs the real CPU with `noun` and `verb`, to check whether the program
+/// halts with `PART2_TARGET_OUTPUT` at position 0
+fn produces_target(program: &[i32], noun: i32, verb: i32) -> bool {
+    let mut program = program.to_vec();
+    set_inputs(&mut program, noun, verb);
+
+    let mut cpu = IntcodeCPU::with_io(program, QueueIO::default());
+    cpu.set_step_limit(Some(PART2_STEP_LIMIT));
+    matches!(cpu.run(), Ok(CPUState::Halted)) && cpu.output() == PART2_TARGET_OUTPUT
+}
 
-            if cpu.output() == PART2_TARGET_OUTPUT {
+fn part2(input: &str) {
+    let program = load_initial_program_state(input);
+
+    match solve_part2_symbolically(&program) {
+        Ok(solutions) => {
+            let checked = solutions
+                .into_iter()
+                .find(|&(noun, verb)| produces_target(&program, noun, verb));
+
+            if let Some((noun, verb)) = checked {
                 println!(
                     "Solution found (noun = {}, verb = {}). Answer is {}",
                     noun,
@@ -66,13 +107,155 @@ fn part2(input: &str) {
                 );
                 return;
             }
+            eprintln!(
+                "WARNING: No symbolic solution checked out on the CPU. Falling back to search"
+            );
         }
+        Err(e) => eprintln!(
+            "WARNING: Could not solve symbolically ({}). Falling back to search",
+            e
+        ),
+    }
+
+    let space = SearchSpace::new()
+        .position(1, PART2_NOUN_MIN..PART2_NOUN_MAX)
+        .position(2, PART2_VERB_MIN..PART2_VERB_MAX);
+    let results = Search::new(&program, space)
+        .step_limit(PART2_STEP_LIMIT)
+        .run(|cpu| cpu.output() == PART2_TARGET_OUTPUT);
+
+    for failure in &results.failures {
+        eprintln!(
+            "WARNING: CPU exception {:?} at position {} while running with inputs ({}). Skipping",
+            failure.exception, failure.pc, failure.assignment
+        );
+    }
+
+    if let Some(solution) = results.matches.first() {
+        let noun = solution.position(1).unwrap();
+        let verb = solution.position(2).unwrap();
+        println!(
+            "Solution found (noun = {}, verb = {}). Answer is {}",
+            noun,
+            verb,
+            (100 * noun + verb)
+        );
+        return;
     }
 
     println!("ERROR: Could not find suitable answer in solution space.");
     std::process::exit(2);
 }
 
+struct BruteForceOptions {
+    target: i32,
+    nouns: Range<i32>,
+    verbs: Range<i32>,
+    threads: usize,
+}
+
+impl Default for BruteForceOptions {
+    fn default() -> Self {
+        BruteForceOptions {
+            target: PART2_TARGET_OUTPUT,
+            nouns: PART2_NOUN_MIN..PART2_NOUN_MAX,
+            verbs: PART2_VERB_MIN..PART2_VERB_MAX,
+            threads: thread::available_parallelism().map_or(1, usize::from),
+        }
+    }
+}
+
+/// Parses a half-open range written as `min..max`
+fn parse_range(s: &str) -> Result<Range<i32>, String> {
+    let (min, max) = s
+        .split_once("..")
+        .ok_or_else(|| format!("Expected a range like 0..100, got '{}'", s))?;
+    let parse = |bound: &str| {
+        i32::from_str(bound).map_err(|_| format!("Could not interpret '{}' as i32", bound))
+    };
+
+    Ok(parse(min)?..parse(max)?)
+}
+
+impl BruteForceOptions {
+    fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Self, String> {
+        let mut opts = Self::default();
+
+        while let Some(flag) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| format!("Missing value for {}", flag))?;
+
+            match flag.as_str() {
+                "--target" => {
+                    opts.target = i32::from_str(&value)
+                        .map_err(|_| format!("Could not interpret '{}' as i32", value))?
+                }
+                "--nouns" => opts.nouns = parse_range(&value)?,
+                "--verbs" => opts.verbs = parse_range(&value)?,
+                "--threads" => {
+                    opts.threads = usize::from_str(&value)
+                        .ok()
+                        .filter(|&threads| threads > 0)
+                        .ok_or_else(|| {
+                            format!("Could not interpret '{}' as a thread count", value)
+                        })?
+                }
+                _ => return Err(format!("Unknown option {}", flag)),
+            }
+        }
+
+        Ok(opts)
+    }
+}
+
+/// Searches the whole noun/verb grid across several threads and reports
+/// every solution rather than just the first
+fn brute_force(input: &str, opts: &BruteForceOptions) {
+    let program = load_initial_program_state(input);
+
+    let space = SearchSpace::new()
+        .position(1, opts.nouns.clone())
+        .position(2, opts.verbs.clone());
+    let searched = space.len();
+    let results = Search::new(&program, space)
+        .threads(opts.threads)
+        .step_limit(PART2_STEP_LIMIT)
+        .run(|cpu| cpu.output() == opts.target);
+
+    println!(
+        "Searched {} noun/verb pairs on {} threads for output {}",
+        searched, opts.threads, opts.target
+    );
+
+    println!("{} solution(s):", results.matches.len());
+    for solution in &results.matches {
+        let noun = solution.position(1).unwrap();
+        let verb = solution.position(2).unwrap();
+        println!(
+            "    noun = {}, verb = {}. Answer is {}",
+            noun,
+            verb,
+            (100 * noun + verb)
+        );
+    }
+
+    println!("{} skipped after CPU exceptions:", results.failures.len());
+    for failure in &results.failures {
+        println!(
+            "    noun = {}, verb = {}: {} at position {}",
+            failure.assignment.position(1).unwrap(),
+            failure.assignment.position(2).unwrap(),
+            failure.exception,
+            failure.pc
+        );
+    }
+
+    if results.matches.is_empty() {
+        std::process::exit(2);
+    }
+}
+
 fn main() {
     let input = include_str!("../../../input/day02/input");
     let mut args = env::args();
@@ -80,13 +263,24 @@ fn main() {
     let prog_name = args.next().expect("unable to get program name");
 
     let maybe_arg = args.next();
-    let maybe_arg_str = maybe_arg.as_ref().map(String::as_str);
 
-    match maybe_arg_str {
+    match maybe_arg.as_deref() {
         Some("part1") => part1(input),
         Some("part2") => part2(input),
+        Some("taint") => taint(input),
+        Some("brute-force") => match BruteForceOptions::parse(args) {
+            Ok(opts) => brute_force(input, &opts),
+            Err(e) => {
+                eprintln!("ERROR: {}", e);
+                eprintln!(
+                    "usage: {} brute-force [--target N] [--nouns MIN..MAX] [--verbs MIN..MAX] [--threads N]",
+                    prog_name
+                );
+                std::process::exit(1);
+            }
+        },
         _ => {
-            eprintln!("usage: {} (part1|part2)", prog_name);
+            eprintln!("usage: {} (part1|part2|taint|brute-force)", prog_name);
             std::process::exit(1);
         }
     }
@@ -99,7 +293,7 @@ mod tests {
     #[test]
     fn aoc19_day2_part1_example_1() {
         let input = "1,9,10,3,2,3,11,0,99,30,40,50";
-        let expected_state: &[u32] = &[3500, 9, 10, 70, 2, 3, 11, 0, 99, 30, 40, 50][..];
+        let expected_state: &[i32] = &[3500, 9, 10, 70, 2, 3, 11, 0, 99, 30, 40, 50][..];
 
         let prog = load_initial_program_state(input);
         let mut cpu = IntcodeCPU::new(prog);
@@ -112,7 +306,7 @@ mod tests {
     #[test]
     fn aoc19_day2_part1_example_2() {
         let input = "1,0,0,0,99";
-        let expected_state: &[u32] = &[2, 0, 0, 0, 99][..];
+        let expected_state: &[i32] = &[2, 0, 0, 0, 99][..];
 
         let prog = load_initial_program_state(input);
         let mut cpu = IntcodeCPU::new(prog);
@@ -125,7 +319,7 @@ mod tests {
     #[test]
     fn aoc19_day2_part1_example_3() {
         let input = "2,3,0,3,99";
-        let expected_state: &[u32] = &[2, 3, 0, 6, 99][..];
+        let expected_state: &[i32] = &[2, 3, 0, 6, 99][..];
 
         let prog = load_initial_program_state(input);
         let mut cpu = IntcodeCPU::new(prog);
@@ -138,7 +332,7 @@ mod tests {
     #[test]
     fn aoc19_day2_part1_example_4() {
         let input = "2,4,4,5,99,0";
-        let expected_state: &[u32] = &[2, 4, 4, 5, 99, 9801][..];
+        let expected_state: &[i32] = &[2, 4, 4, 5, 99, 9801][..];
 
         let prog = load_initial_program_state(input);
         let mut cpu = IntcodeCPU::new(prog);
@@ -151,7 +345,7 @@ mod tests {
     #[test]
     fn aoc19_day2_part1_example_5() {
         let input = "1,1,1,4,99,5,6,0,99";
-        let expected_state: &[u32] = &[30, 1, 1, 4, 2, 5, 6, 0, 99][..];
+        let expected_state: &[i32] = &[30, 1, 1, 4, 2, 5, 6, 0, 99][..];
 
         let prog = load_initial_program_state(input);
         let mut cpu = IntcodeCPU::new(prog);
@@ -160,4 +354,32 @@ mod tests {
 
         assert_eq!(cpu.inspect_state(), expected_state);
     }
+
+    #[test]
+    fn symbolic_solutions_are_checked_on_the_cpu() {
+        let program = load_initial_program_state(include_str!("../../../input/day02/input"));
+
+        let solutions = solve_part2_symbolically(&program).expect("Should solve symbolically");
+        assert!(solutions.contains(&(20, 3)));
+        assert!(produces_target(&program, 20, 3));
+        assert!(!produces_target(&program, 20, 4));
+    }
+
+    #[test]
+    fn brute_force_options_from_args() {
+        let args = ["--target", "42", "--nouns", "5..10", "--threads", "3"]
+            .iter()
+            .map(|s| s.to_string());
+        let opts = BruteForceOptions::parse(args).expect("Options should parse");
+
+        assert_eq!(opts.target, 42);
+        assert_eq!(opts.nouns, 5..10);
+        assert_eq!(opts.verbs, PART2_VERB_MIN..PART2_VERB_MAX);
+        assert_eq!(opts.threads, 3);
+
+        assert!(parse_range("5-10").is_err());
+
+        let args = ["--threads", "0"].iter().map(|s| s.to_string());
+        assert!(BruteForceOptions::parse(args).is_err());
+    }
 }
@@ -1,29 +1,239 @@
 use std::env;
-use std::str::FromStr;
 
-mod intcode;
-
-use intcode::IntcodeCPU;
+use aoc2019::intcode::cfg::ControlFlowGraph;
+use aoc2019::intcode::coverage::Coverage;
+use aoc2019::intcode::io::QueueIO;
+use aoc2019::intcode::loader;
+use aoc2019::intcode::IntcodeCPU;
 
 fn load_initial_program_state(input: &str) -> Vec<i32> {
-    input
-        .split(',')
-        .enumerate()
-        .map(|(i, pos)| {
-            i32::from_str(pos.trim()).unwrap_or_else(|_| {
-                panic!("Could not interpret '{}' at position {} as u32", pos, i)
-            })
+    loader::parse(input).unwrap_or_else(|e| {
+        eprintln!("ERROR: Could not load program: {}", e);
+        std::process::exit(1);
+    })
+}
+
+fn run(input: &str) {
+    let program = load_initial_program_state(input);
+
+    let mut cpu = IntcodeCPU::new(program);
+    cpu.run().expect("Should not have excepted at runtime");
+
+    println!("Program finished");
+}
+
+const PART1_SYSTEM_ID: i32 = 1;
+const PART2_SYSTEM_ID: i32 = 5;
+
+/// Runs the diagnostic program for `system_id`, returning the diagnostic code
+/// if every test output before it is zero
+fn run_diagnostic(program: Vec<i32>, system_id: i32) -> Result<i32, String> {
+    diagnose_on(IntcodeCPU::with_io(program, QueueIO::new(vec![system_id])))
+}
+
+/// Runs a CPU already loaded with the diagnostic program and its system ID
+fn diagnose_on(mut cpu: IntcodeCPU<QueueIO>) -> Result<i32, String> {
+    cpu.run()
+        .map_err(|e| format!("CPU exception {} at position {}", e, cpu.pc()))?;
+
+    let (code, tests) = cpu
+        .io()
+        .outputs()
+        .split_last()
+        .ok_or("Program produced no output")?;
+
+    if let Some((n, result)) = tests.iter().enumerate().find(|(_, &result)| result != 0) {
+        return Err(format!("Test {} failed with output {}", n + 1, result));
+    }
+
+    Ok(*code)
+}
+
+fn diagnose(input: &str, system_id: i32) {
+    let program = load_initial_program_state(input);
+
+    match run_diagnostic(program, system_id) {
+        Ok(code) => println!("Diagnostic code: {}", code),
+        Err(e) => {
+            println!("ERROR: {}", e);
+            std::process::exit(2);
+        }
+    }
+}
+
+/// Runs the diagnostic for `system_id` recording coverage
+fn covered_run(program: Vec<i32>, system_id: i32) -> Result<Coverage, String> {
+    let mut cpu = IntcodeCPU::with_io(program, QueueIO::new(vec![system_id]));
+    cpu.enable_coverage();
+    cpu.run()
+        .map_err(|e| format!("CPU exception {} at position {}", e, cpu.pc()))?;
+
+    Ok(cpu.coverage().expect("Coverage was enabled").clone())
+}
+
+/// Reruns both diagnostics with the code either of them executes
+/// write-protected, apart from the cells the program patches on purpose,
+/// returning their diagnostic codes
+fn strict_diagnostics(program: &[i32]) -> Result<Vec<i32>, String> {
+    let system_ids = [PART1_SYSTEM_ID, PART2_SYSTEM_ID];
+    let runs = system_ids
+        .iter()
+        .map(|&system_id| covered_run(program.to_vec(), system_id))
+        .collect::<Result<Vec<_>, _>>()?;
+    let runs = runs.iter().collect::<Vec<_>>();
+
+    system_ids
+        .iter()
+        .map(|&system_id| {
+            let mut cpu = IntcodeCPU::with_io(program.to_vec(), QueueIO::new(vec![system_id]));
+            cpu.protect_code(&runs);
+            diagnose_on(cpu)
         })
         .collect()
 }
 
-fn main() {
-    let input = include_str!("../../../input/day05/input");
+/// Runs with the program's code write-protected, reporting the first
+/// unexpected write into an instruction instead of executing the modified
+/// code
+fn strict(input: &str) {
+    let program = load_initial_program_state(input);
+
+    match strict_diagnostics(&program) {
+        Ok(codes) => {
+            for code in codes {
+                println!("Diagnostic code: {}", code);
+            }
+        }
+        Err(e) => {
+            eprintln!("ERROR: {}", e);
+            std::process::exit(2);
+        }
+    }
+}
+
+/// Graphs the code the diagnostic for `system_id` executed. The program
+/// patches its own code after reading the ID, so the initial image alone
+/// only shows the first few instructions.
+fn executed_cfg(program: Vec<i32>, system_id: i32) -> Result<ControlFlowGraph, String> {
+    let coverage = covered_run(program.clone(), system_id)?;
+    Ok(ControlFlowGraph::from_coverage(&program, &coverage))
+}
+
+fn cfg(input: &str, system_id: i32) {
+    let program = load_initial_program_state(input);
+
+    match executed_cfg(program, system_id) {
+        Ok(cfg) => print!("{}", cfg.to_dot()),
+        Err(e) => {
+            eprintln!("ERROR: {}", e);
+            std::process::exit(2);
+        }
+    }
+}
 
-    let mut program = load_initial_program_state(input);
+fn coverage(input: &str) {
+    let program = load_initial_program_state(input);
 
     let mut cpu = IntcodeCPU::new(program);
+    cpu.enable_coverage();
     cpu.run().expect("Should not have excepted at runtime");
 
-    println!("Program finished");
+    let coverage = cpu.coverage().expect("Coverage was enabled");
+
+    println!("{}", coverage.heatmap(64));
+    let image = coverage.executed_image(&cpu.inspect_state());
+    print!("{}", coverage.annotated_disassembly(&image));
+}
+
+fn profile(input: &str) {
+    let program = load_initial_program_state(input);
+
+    let mut cpu = IntcodeCPU::new(program);
+    cpu.enable_profiling();
+    cpu.run().expect("Should not have excepted at runtime");
+
+    print!(
+        "{}",
+        cpu.profile().expect("Profiling was enabled").report(20)
+    );
+}
+
+fn main() {
+    let input = include_str!("../../../input/day05/input");
+    let mut args = env::args();
+
+    let prog_name = args.next().expect("unable to get program name");
+
+    let maybe_arg = args.next();
+
+    match maybe_arg.as_deref() {
+        None => run(input),
+        Some("part1") => diagnose(input, PART1_SYSTEM_ID),
+        Some("part2") => diagnose(input, PART2_SYSTEM_ID),
+        Some("strict") => strict(input),
+        Some("cfg") => match args.next().as_deref() {
+            None | Some("part2") => cfg(input, PART2_SYSTEM_ID),
+            Some("part1") => cfg(input, PART1_SYSTEM_ID),
+            Some(other) => {
+                eprintln!("ERROR: Unknown part {}", other);
+                eprintln!("usage: {} cfg [part1|part2]", prog_name);
+                std::process::exit(1);
+            }
+        },
+        Some("coverage") => coverage(input),
+        Some("profile") => profile(input),
+        _ => {
+            eprintln!(
+                "usage: {} [part1|part2|strict|cfg [part1|part2]|coverage|profile]",
+                prog_name
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diagnostic_code_follows_passing_tests() {
+        let program = vec![3, 11, 104, 0, 104, 0, 4, 11, 99, 0, 0, 0];
+
+        assert_eq!(run_diagnostic(program, 5), Ok(5));
+    }
+
+    #[test]
+    fn failing_test_is_reported() {
+        let program = vec![104, 0, 104, 3, 104, 42, 99];
+
+        assert_eq!(
+            run_diagnostic(program, 1),
+            Err("Test 2 failed with output 3".to_string())
+        );
+    }
+
+    #[test]
+    fn strict_mode_passes_on_the_input() {
+        let program = load_initial_program_state(include_str!("../../../input/day05/input"));
+
+        assert_eq!(strict_diagnostics(&program), Ok(vec![7692125, 14340395]));
+    }
+
+    #[test]
+    fn cfg_follows_the_patched_code() {
+        let program = load_initial_program_state(include_str!("../../../input/day05/input"));
+
+        for &system_id in &[PART1_SYSTEM_ID, PART2_SYSTEM_ID] {
+            let cfg = executed_cfg(program.clone(), system_id).expect("Diagnostic should run");
+
+            // The only faults are the jumps to 99999 taken if a test fails
+            assert!(cfg.disassembly(&program).contains("HALT"));
+            assert!(cfg
+                .blocks()
+                .filter_map(|block| block.fault())
+                .all(|(addr, _)| *addr == 99999));
+            assert_eq!(cfg.instruction_containing(6).map(|(addr, _)| addr), Some(6));
+        }
+    }
 }
@@ -0,0 +1,564 @@
+use std::collections::BTreeSet;
+use std::env;
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+
+use aoc2019::intcode::cfg::ControlFlowGraph;
+use aoc2019::intcode::diff::MemoryDiff;
+use aoc2019::intcode::display::{Palette, Screen};
+use aoc2019::intcode::io::{AsciiIO, IntcodeIO, QueueIO};
+use aoc2019::intcode::joystick::{self, Arcade, Joystick, Keyboard, Policy, Scripted};
+use aoc2019::intcode::loader;
+use aoc2019::intcode::observer::{Observer, Trace};
+use aoc2019::intcode::robot::{self, Hull, PaintingRobot, BLACK, WHITE};
+use aoc2019::intcode::{CPUResult, CPUState, IntcodeCPU};
+
+const USAGE: &str = "(run|disasm|trace|debug|diff|screen|arcade|robot) <program|-> \
+                     [--input 1,5] [--input-file PATH] [--set POS=VAL]... [--memory] \
+                     [--annotate] [--run] [--step-limit N] [--ascii] [--live] [--frames DIR] \
+                     [--script PATH] [--auto] [--start-white]";
+
+#[derive(Default)]
+struct Options {
+    program: String,
+    inputs: Vec<i32>,
+    pokes: Vec<(usize, i32)>,
+    memory: bool,
+    annotate: bool,
+    run: bool,
+    step_limit: Option<u64>,
+    ascii: bool,
+    live: bool,
+    frames: Option<String>,
+    script: Option<String>,
+    auto: bool,
+    start_white: bool,
+}
+
+fn parse_poke(s: &str) -> Result<(usize, i32), String> {
+    let (pos, val) = s
+        .split_once('=')
+        .ok_or_else(|| format!("Expected POS=VAL, got '{}'", s))?;
+    let pos = usize::from_str(pos.trim()).map_err(|_| format!("Invalid position '{}'", pos))?;
+    let val = i32::from_str(val.trim()).map_err(|_| format!("Invalid value '{}'", val))?;
+
+    Ok((pos, val))
+}
+
+impl Options {
+    fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Self, String> {
+        let mut opts = Options {
+            program: args.next().ok_or("Missing program path")?,
+            ..Options::default()
+        };
+
+        while let Some(flag) = args.next() {
+            let mut value = || args.next().ok_or(format!("Missing value for {}", flag));
+
+            match flag.as_str() {
+                "--input" => {
+                    let inputs = loader::parse(&value()?).map_err(|e| format!("--input {}", e))?;
+                    opts.inputs.extend(inputs);
+                }
+                "--input-file" => {
+                    let path = value()?;
+                    let inputs =
+                        loader::load_path(&path).map_err(|e| format!("{}: {}", path, e))?;
+                    opts.inputs.extend(inputs);
+                }
+                "--set" => opts.pokes.push(parse_poke(&value()?)?),
+                "--step-limit" => {
+                    let limit = value()?;
+                    opts.step_limit = Some(
+                        u64::from_str(&limit)
+                            .map_err(|_| format!("Invalid step limit '{}'", limit))?,
+                    );
+                }
+                "--memory" => opts.memory = true,
+                "--annotate" => opts.annotate = true,
+                "--run" => opts.run = true,
+                "--ascii" => opts.ascii = true,
+                "--live" => opts.live = true,
+                "--frames" => opts.frames = Some(value()?),
+                "--script" => opts.script = Some(value()?),
+                "--auto" => opts.auto = true,
+                "--start-white" => opts.start_white = true,
+                _ => return Err(format!("Unknown option {}", flag)),
+            }
+        }
+
+        Ok(opts)
+    }
+
+    /// Loads the program and applies the `--set` pokes
+    fn load(&self) -> Result<Vec<i32>, String> {
+        let mut program = if self.program == "-" {
+            loader::load_stdin()
+        } else {
+            loader::load_path(&self.program)
+        }
+        .map_err(|e| format!("{}: {}", self.program, e))?;
+
+        for &(pos, val) in &self.pokes {
+            let cell = program
+                .get_mut(pos)
+                .ok_or_else(|| format!("--set {}={}: position is outside the program", pos, val))?;
+            *cell = val;
+        }
+
+        Ok(program)
+    }
+
+    /// A screen for the arcade palette, writing frames if asked to
+    fn screen(&self, live: bool) -> Screen {
+        let mut screen = Screen::new(Palette::arcade());
+        if live {
+            screen = screen.live();
+        }
+        if let Some(dir) = &self.frames {
+            screen = screen.frame_dir(dir);
+        }
+        screen
+    }
+
+    fn cpu(&self) -> Result<IntcodeCPU<QueueIO>, String> {
+        Ok(self.cpu_for(self.load()?))
+    }
+
+    /// A CPU for an already loaded program, since stdin can only be read
+    /// once
+    fn cpu_for(&self, program: Vec<i32>) -> IntcodeCPU<QueueIO> {
+        self.cpu_with(program, QueueIO::new(self.inputs.clone()))
+    }
+
+    /// A CPU wired to `io` instead of the `--input` queue
+    fn cpu_with<IO: IntcodeIO>(&self, program: Vec<i32>, io: IO) -> IntcodeCPU<IO> {
+        let mut cpu = IntcodeCPU::with_io(program, io);
+        cpu.set_step_limit(self.step_limit);
+        cpu
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn json_array(values: &[i32]) -> String {
+    let values = values.iter().map(i32::to_string).collect::<Vec<_>>();
+    format!("[{}]", values.join(","))
+}
+
+/// Describes how a run ended as a JSON object
+fn summary<O: Observer>(
+    cpu: &IntcodeCPU<QueueIO, O>,
+    result: &CPUResult<CPUState>,
+    memory: bool,
+) -> String {
+    let mut fields = vec![];
+
+    match result {
+        Ok(state) => fields.push(format!(
+            "\"state\":{}",
+            json_string(&format!("{:?}", state))
+        )),
+        Err(ex) => {
+            fields.push("\"state\":\"Exception\"".to_string());
+            fields.push(format!("\"exception\":{}", json_string(&ex.to_string())));
+        }
+    }
+    fields.push(format!("\"pc\":{}", cpu.pc()));
+    fields.push(format!("\"steps\":{}", cpu.steps()));
+    fields.push(format!("\"outputs\":{}", json_array(cpu.io().outputs())));
+    if memory {
+        fields.push(format!("\"memory\":{}", json_array(&cpu.inspect_state())));
+    }
+
+    format!("{{{}}}", fields.join(","))
+}
+
+fn finish<O: Observer>(cpu: &IntcodeCPU<QueueIO, O>, result: CPUResult<CPUState>, opts: &Options) {
+    println!("{}", summary(cpu, &result, opts.memory));
+
+    match result {
+        Ok(CPUState::Halted) => {}
+        Ok(_) => {
+            eprintln!("ERROR: Program is waiting for more input than was given");
+            std::process::exit(2);
+        }
+        Err(_) => std::process::exit(2),
+    }
+}
+
+fn run(opts: &Options) -> Result<(), String> {
+    if opts.ascii {
+        return converse(opts);
+    }
+
+    let mut cpu = opts.cpu()?;
+    let result = cpu.run();
+    finish(&cpu, result, opts);
+    Ok(())
+}
+
+/// Runs an ASCII program on the terminal, printing its lines as they are
+/// output and reading a line of input whenever it asks
+fn converse(opts: &Options) -> Result<(), String> {
+    if !opts.inputs.is_empty() {
+        return Err("--ascii reads input from the terminal, not --input".to_string());
+    }
+
+    let mut cpu = opts.cpu_with(opts.load()?, AsciiIO::interactive());
+    let result = cpu.run();
+
+    // Whatever followed the last newline, e.g. the program's final answer
+    if !cpu.io().partial_line().is_empty() {
+        println!("{}", cpu.io().partial_line());
+    }
+
+    match result {
+        Ok(CPUState::Halted) => {}
+        Ok(_) => {
+            eprintln!("ERROR: Program is waiting for more input than was given");
+            std::process::exit(2);
+        }
+        Err(ex) => {
+            eprintln!("ERROR: CPU exception {} at position {}", ex, cpu.pc());
+            std::process::exit(2);
+        }
+    }
+    Ok(())
+}
+
+/// Lists the program as code and data. With `--run` the code is what a run
+/// executed, as it was when it ran, so code the program patches is found.
+fn disasm(opts: &Options) -> Result<(), String> {
+    let program = opts.load()?;
+
+    let cfg = if opts.run {
+        let mut cpu = opts.cpu_for(program.clone());
+        cpu.enable_coverage();
+        if let Err(ex) = cpu.run() {
+            eprintln!("ERROR: CPU exception {} at position {}", ex, cpu.pc());
+            std::process::exit(2);
+        }
+        ControlFlowGraph::from_coverage(&program, cpu.coverage().expect("Coverage was enabled"))
+    } else {
+        ControlFlowGraph::build(&program)
+    };
+
+    print!("{}", cfg.disassembly(&program));
+    Ok(())
+}
+
+/// Runs the program and lists the cells it changed, relative to the image
+/// after `--set` pokes were applied
+fn diff(opts: &Options) -> Result<(), String> {
+    let program = opts.load()?;
+    let mut cpu = opts.cpu_for(program.clone());
+    let result = cpu.run();
+
+    let cfg = if opts.annotate {
+        Some(ControlFlowGraph::build(&program))
+    } else {
+        None
+    };
+    print!(
+        "{}",
+        MemoryDiff::between(&program, &cpu.inspect_state()).report(cfg.as_ref())
+    );
+
+    if let Err(ex) = result {
+        eprintln!("ERROR: CPU exception {} at position {}", ex, cpu.pc());
+        std::process::exit(2);
+    }
+    Ok(())
+}
+
+/// Runs a program drawing on a screen to the end, printing the final
+/// screen unless it was drawn live
+fn show<IO, F>(opts: &Options, io: IO, live: bool, screen: F) -> Result<(), String>
+where
+    IO: IntcodeIO,
+    F: Fn(&mut IO) -> &mut Screen,
+{
+    let mut cpu = opts.cpu_with(opts.load()?, io);
+    let result = cpu.run();
+
+    let screen = screen(cpu.io_mut());
+    if let Ok(CPUState::Halted) = result {
+        screen
+            .end_frame()
+            .map_err(|e| format!("Could not draw frame: {}", e))?;
+    }
+    if let Some(e) = screen.error() {
+        return Err(format!("Could not draw frame: {}", e));
+    }
+    if !live {
+        print!("{}", screen.render());
+    }
+
+    match result {
+        Ok(CPUState::Halted) => {}
+        Ok(_) => {
+            eprintln!("ERROR: Program is waiting for more input than was given");
+            std::process::exit(2);
+        }
+        Err(ex) => {
+            eprintln!("ERROR: CPU exception {} at position {}", ex, cpu.pc());
+            std::process::exit(2);
+        }
+    }
+    Ok(())
+}
+
+/// Runs the program with its outputs drawn as `(x, y, tile)` triples
+fn screen(opts: &Options) -> Result<(), String> {
+    show(opts, opts.screen(opts.live), opts.live, |screen| screen)
+}
+
+fn play<J: Joystick>(opts: &Options, joystick: J, live: bool) -> Result<(), String> {
+    let arcade = Arcade::new(opts.screen(live), joystick);
+    show(opts, arcade, live, Arcade::screen_mut)
+}
+
+/// Plays an arcade game with the arrow keys, a script of moves, or by
+/// following the ball
+fn arcade(opts: &Options) -> Result<(), String> {
+    if let Some(path) = &opts.script {
+        let script = Scripted::load_path(path).map_err(|e| format!("{}: {}", path, e))?;
+        play(opts, script, opts.live)
+    } else if opts.auto {
+        play(opts, Policy::new(joystick::track_ball), opts.live)
+    } else {
+        let keyboard =
+            Keyboard::new().map_err(|e| format!("Could not set up the terminal: {}", e))?;
+        play(opts, keyboard, true)
+    }
+}
+
+/// Runs a hull painting robot, reporting how many panels it painted and
+/// drawing the white ones
+fn paint(opts: &Options) -> Result<(), String> {
+    let mut hull = Hull::default();
+    hull.set_color((0, 0), if opts.start_white { WHITE } else { BLACK });
+    let mut cpu = opts.cpu_with(opts.load()?, PaintingRobot::on(hull));
+
+    match robot::run(&mut cpu) {
+        Ok(()) => {
+            let hull = cpu.io().hull();
+            println!("Panels painted: {}", hull.painted());
+            print!("{}", hull.render());
+            Ok(())
+        }
+        Err(ex) => {
+            eprintln!("ERROR: CPU exception {} at position {}", ex, cpu.pc());
+            std::process::exit(2);
+        }
+    }
+}
+
+/// Executes one instruction, printing what the trace recorded and anything
+/// it outputs
+fn traced_step(cpu: &mut IntcodeCPU<QueueIO, Trace>) -> CPUResult<CPUState> {
+    let outputs = cpu.io().outputs().len();
+    let result = cpu.step();
+
+    for line in cpu.observer_mut().take_lines() {
+        println!("{}", line);
+    }
+    for val in &cpu.io().outputs()[outputs..] {
+        println!("        -> {}", val);
+    }
+
+    result
+}
+
+fn trace(opts: &Options) -> Result<(), String> {
+    let mut cpu = opts.cpu()?.with_observer(Trace::default());
+
+    let result = loop {
+        match traced_step(&mut cpu) {
+            Ok(CPUState::Running) => continue,
+            other => break other,
+        }
+    };
+
+    finish(&cpu, result, opts);
+    Ok(())
+}
+
+const DEBUG_HELP: &str = "\
+commands:
+  s [N]        step N instructions (default 1)
+  c            continue to a breakpoint, halt or exception
+  b ADDR       toggle a breakpoint
+  x ADDR [N]   show N memory cells from ADDR (default 1)
+  in V...      queue input values
+  i            show pc, state and outputs
+  q            quit";
+
+fn debug(opts: &Options) -> Result<(), String> {
+    let mut cpu = opts.cpu()?.with_observer(Trace::default());
+    let mut breakpoints = BTreeSet::new();
+    let mut last: CPUResult<CPUState> = Ok(CPUState::Running);
+
+    let stdin = io::stdin();
+    println!("{}", DEBUG_HELP);
+
+    loop {
+        match cpu.current_instruction() {
+            Ok(instruction) => print!("{:>6}  {}\n(intcode) ", cpu.pc(), instruction),
+            Err(ex) => print!("{:>6}  <{}>\n(intcode) ", cpu.pc(), ex),
+        }
+        io::stdout().flush().unwrap();
+
+        let mut line = String::new();
+        if stdin
+            .lock()
+            .read_line(&mut line)
+            .map_err(|e| e.to_string())?
+            == 0
+        {
+            break;
+        }
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        let arg = |n: usize| words.get(n).and_then(|w| usize::from_str(w).ok());
+
+        match words.first().copied() {
+            Some("s") => {
+                for _ in 0..arg(1).unwrap_or(1) {
+                    last = traced_step(&mut cpu);
+                    if !matches!(last, Ok(CPUState::Running)) {
+                        break;
+                    }
+                }
+            }
+            Some("c") => {
+                let outputs = cpu.io().outputs().len();
+                loop {
+                    last = cpu.step();
+                    if !matches!(last, Ok(CPUState::Running)) {
+                        break;
+                    }
+                    if breakpoints.contains(&cpu.pc()) {
+                        println!("Breakpoint at {}", cpu.pc());
+                        break;
+                    }
+                }
+                // Only the instruction stopped at is shown
+                cpu.observer_mut().take_lines();
+                for val in &cpu.io().outputs()[outputs..] {
+                    println!("        -> {}", val);
+                }
+            }
+            Some("b") => match arg(1) {
+                Some(addr) if breakpoints.remove(&addr) => {
+                    println!("Removed breakpoint at {}", addr)
+                }
+                Some(addr) => {
+                    breakpoints.insert(addr);
+                    println!("Breakpoint at {}", addr);
+                }
+                None => println!("usage: b ADDR"),
+            },
+            Some("x") => match arg(1) {
+                Some(addr) => {
+                    for pos in addr..addr.saturating_add(arg(2).unwrap_or(1)) {
+                        match cpu.get_position(pos) {
+                            Some(val) => println!("  [{}] {}", pos, val),
+                            None => break,
+                        }
+                    }
+                }
+                None => println!("usage: x ADDR [N]"),
+            },
+            Some("in") => {
+                for word in &words[1..] {
+                    match i32::from_str(word) {
+                        Ok(val) => cpu.io_mut().push_input(val),
+                        Err(_) => println!("Could not interpret '{}' as i32", word),
+                    }
+                }
+            }
+            Some("i") => println!("{}", summary(&cpu, &last, opts.memory)),
+            Some("q") => break,
+            Some(_) => println!("{}", DEBUG_HELP),
+            None => {}
+        }
+
+        if let Err(ex) = &last {
+            println!("Exception: {}", ex);
+        }
+    }
+
+    Ok(())
+}
+
+fn main() {
+    let mut args = env::args();
+
+    let prog_name = args.next().expect("unable to get program name");
+
+    let maybe_arg = args.next();
+
+    let command: fn(&Options) -> Result<(), String> = match maybe_arg.as_deref() {
+        Some("run") => run,
+        Some("disasm") => disasm,
+        Some("trace") => trace,
+        Some("debug") => debug,
+        Some("diff") => diff,
+        Some("screen") => screen,
+        Some("arcade") => arcade,
+        Some("robot") => paint,
+        _ => {
+            eprintln!("usage: {} {}", prog_name, USAGE);
+            std::process::exit(1);
+        }
+    };
+
+    if let Err(e) = Options::parse(args).and_then(|opts| command(&opts)) {
+        eprintln!("ERROR: {}", e);
+        eprintln!("usage: {} {}", prog_name, USAGE);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn options_from_args() {
+        let args = ["prog.txt", "--input", "1,5", "--set", "1=12", "--memory"]
+            .iter()
+            .map(|s| s.to_string());
+        let opts = Options::parse(args).expect("Options should parse");
+
+        assert_eq!(opts.program, "prog.txt");
+        assert_eq!(opts.inputs, vec![1, 5]);
+        assert_eq!(opts.pokes, vec![(1, 12)]);
+        assert!(opts.memory);
+        assert!(parse_poke("1:12").is_err());
+    }
+
+    #[test]
+    fn summary_is_json() {
+        let mut cpu = IntcodeCPU::with_io(vec![104, 7, 99], QueueIO::default());
+        let result = cpu.run();
+
+        assert_eq!(
+            summary(&cpu, &result, true),
+            "{\"state\":\"Halted\",\"pc\":2,\"steps\":2,\"outputs\":[7],\"memory\":[104,7,99]}"
+        );
+    }
+}
@@ -0,0 +1,433 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use super::coverage::Coverage;
+use super::{CPUException, CPUOp, CPUResult, Operand};
+
+/// Where control goes when an edge is followed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Target {
+    /// The destination address is encoded in the instruction itself
+    Direct(usize),
+    /// The destination address is read from this cell at runtime
+    Indirect(usize),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EdgeKind {
+    Taken,
+    NotTaken,
+    Fallthrough,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Edge {
+    pub kind: EdgeKind,
+    pub target: Target,
+}
+
+pub struct BasicBlock {
+    start: usize,
+    end: usize,
+    instructions: Vec<(usize, CPUOp)>,
+    fault: Option<(usize, CPUException)>,
+    successors: Vec<Edge>,
+}
+
+impl BasicBlock {
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// One past the last cell of the last instruction in the block
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    pub fn successors(&self) -> &[Edge] {
+        &self.successors
+    }
+
+    /// Address and reason if control runs into something that can't be decoded
+    pub fn fault(&self) -> Option<&(usize, CPUException)> {
+        self.fault.as_ref()
+    }
+
+    pub fn instruction_addresses(&self) -> impl Iterator<Item = usize> + '_ {
+        self.instructions.iter().map(|(addr, _)| *addr)
+    }
+}
+
+/// Basic blocks of the code reachable from address 0, found by following
+/// jumps in the initial image. Jump conditions and targets given in immediate
+/// mode are taken at face value, so code that rewrites its own jumps at
+/// runtime isn't modelled by `build`; `from_coverage` graphs what a run
+/// actually executed instead.
+pub struct ControlFlowGraph {
+    blocks: BTreeMap<usize, BasicBlock>,
+}
+
+fn instruction_len(op: &CPUOp) -> usize {
+    op.next_pc_offset().max(1)
+}
+
+fn jump_edges(addr: usize, cmp: &Operand, to: &Operand, jump_if_zero: bool) -> Vec<Edge> {
+    let target = match *to {
+        Operand::Immediate(to) => Target::Direct(to as usize),
+        Operand::Position(cell) => Target::Indirect(cell),
+    };
+    let taken = Edge {
+        kind: EdgeKind::Taken,
+        target,
+    };
+    let not_taken = Edge {
+        kind: EdgeKind::NotTaken,
+        target: Target::Direct(addr + 3),
+    };
+
+    match *cmp {
+        Operand::Immediate(val) if (val == 0) == jump_if_zero => vec![taken],
+        Operand::Immediate(_) => vec![not_taken],
+        Operand::Position(_) => vec![taken, not_taken],
+    }
+}
+
+fn successors(addr: usize, op: &CPUOp) -> Vec<Edge> {
+    match op {
+        CPUOp::Halt | CPUOp::Undefined(_) => vec![],
+        CPUOp::JumpZero { cmp, to } => jump_edges(addr, cmp, to, true),
+        CPUOp::JumpNonZero { cmp, to } => jump_edges(addr, cmp, to, false),
+        _ => vec![Edge {
+            kind: EdgeKind::Fallthrough,
+            target: Target::Direct(addr + instruction_len(op)),
+        }],
+    }
+}
+
+fn escape_label(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+impl ControlFlowGraph {
+    pub fn build(image: &[i32]) -> Self {
+        Self::build_from(image, vec![])
+    }
+
+    /// The graph of the code a run executed, decoded as it was when it ran
+    /// rather than from `image`. Unlike `build`, this finds code the program
+    /// patched before running it, and code only reached through indirect
+    /// jumps.
+    pub fn from_coverage(image: &[i32], coverage: &Coverage) -> Self {
+        Self::build_from(
+            &coverage.executed_image(image),
+            coverage.executed_addresses(),
+        )
+    }
+
+    /// Decodes from address 0 and each of `entries`. An entry starts a
+    /// block of its own unless some decoded instruction falls through to it.
+    fn build_from<I: IntoIterator<Item = usize>>(image: &[i32], entries: I) -> Self {
+        let fetch = |addr: usize| image.get(addr).copied();
+
+        let mut decoded: BTreeMap<usize, CPUResult<CPUOp>> = BTreeMap::new();
+        let mut leaders = BTreeSet::new();
+        let mut fallthroughs = BTreeSet::new();
+        let entries = entries.into_iter().collect::<Vec<usize>>();
+        let mut worklist = entries.clone();
+        worklist.push(0);
+        leaders.insert(0);
+
+        while let Some(addr) = worklist.pop() {
+            if decoded.contains_key(&addr) {
+                continue;
+            }
+
+            // An undefined opcode ends control flow just like a cell that
+            // can't be decoded at all
+            let op = CPUOp::decode(addr, fetch, false).and_then(|op| match op {
+                CPUOp::Undefined(opcode) => Err(CPUException::invalid_opcode(opcode)),
+                op => Ok(op),
+            });
+            if let Ok(ref op) = op {
+                for edge in successors(addr, op) {
+                    if let Target::Direct(to) = edge.target {
+                        if edge.kind != EdgeKind::Fallthrough {
+                            leaders.insert(to);
+                        } else {
+                            fallthroughs.insert(to);
+                        }
+                        worklist.push(to);
+                    }
+                }
+            }
+            decoded.insert(addr, op);
+        }
+
+        leaders.extend(
+            entries
+                .into_iter()
+                .filter(|entry| !fallthroughs.contains(entry)),
+        );
+
+        let mut blocks = BTreeMap::new();
+
+        for &leader in &leaders {
+            let mut block = BasicBlock {
+                start: leader,
+                end: leader,
+                instructions: vec![],
+                fault: None,
+                successors: vec![],
+            };
+            let mut addr = leader;
+
+            while let Some(op) = decoded.remove(&addr) {
+                match op {
+                    Ok(op) => {
+                        let succ = successors(addr, &op);
+                        block.end = addr + instruction_len(&op);
+                        block.instructions.push((addr, op));
+
+                        let terminates = succ.len() != 1 || succ[0].kind != EdgeKind::Fallthrough;
+                        if terminates || leaders.contains(&block.end) {
+                            block.successors = succ;
+                            break;
+                        }
+                        addr = block.end;
+                    }
+                    Err(ex) => {
+                        block.fault = Some((addr, ex));
+                        break;
+                    }
+                }
+            }
+
+            blocks.insert(leader, block);
+        }
+
+        ControlFlowGraph { blocks }
+    }
+
+    pub fn blocks(&self) -> impl Iterator<Item = &BasicBlock> {
+        self.blocks.values()
+    }
+
+    pub fn block_at(&self, start: usize) -> Option<&BasicBlock> {
+        self.blocks.get(&start)
+    }
+
+    /// Address and disassembly of the reachable instruction occupying
+    /// `addr`, whether as its opcode or one of its parameters
+    pub fn instruction_containing(&self, addr: usize) -> Option<(usize, String)> {
+        let (_, block) = self.blocks.range(..=addr).next_back()?;
+
+        block
+            .instructions
+            .iter()
+            .find(|(start, op)| (*start..start + instruction_len(op)).contains(&addr))
+            .map(|(start, op)| (*start, op.to_string()))
+    }
+
+    /// Lists `image` with the instructions reachable in the graph
+    /// disassembled and every other cell as raw data
+    pub fn disassembly(&self, image: &[i32]) -> String {
+        let instructions = self
+            .blocks
+            .values()
+            .flat_map(|block| block.instructions.iter())
+            .map(|(addr, op)| (*addr, op))
+            .collect::<BTreeMap<usize, &CPUOp>>();
+
+        let mut out = String::new();
+        let mut pos = 0;
+
+        while pos < image.len() {
+            match instructions.get(&pos) {
+                Some(op) => {
+                    writeln!(out, "{:>6}  {}", pos, op).unwrap();
+                    pos += instruction_len(op);
+                }
+                None => {
+                    writeln!(out, "{:>6}  DATA {}", pos, image[pos]).unwrap();
+                    pos += 1;
+                }
+            }
+        }
+
+        out
+    }
+
+    /// Renders the graph in Graphviz DOT format, one node per block labelled
+    /// with its disassembly.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::new();
+        let mut indirect_cells = BTreeSet::new();
+
+        writeln!(out, "digraph cfg {{").unwrap();
+        writeln!(out, "    node [shape=box, fontname=\"monospace\"];").unwrap();
+
+        for block in self.blocks.values() {
+            let mut label = format!("{}..{}\\l", block.start, block.end);
+            for (addr, op) in &block.instructions {
+                label += &format!("{}: {}\\l", addr, escape_label(&op.to_string()));
+            }
+            if let Some((addr, ex)) = &block.fault {
+                label += &format!("{}: FAULT {}\\l", addr, escape_label(ex.message()));
+            }
+
+            writeln!(out, "    b{} [label=\"{}\"];", block.start, label).unwrap();
+        }
+
+        for block in self.blocks.values() {
+            for edge in &block.successors {
+                let to = match edge.target {
+                    Target::Direct(addr) => format!("b{}", addr),
+                    Target::Indirect(cell) => {
+                        indirect_cells.insert(cell);
+                        format!("ind{}", cell)
+                    }
+                };
+                let attrs = match edge.kind {
+                    EdgeKind::Taken => " [label=\"taken\", color=darkgreen]",
+                    EdgeKind::NotTaken => " [label=\"not taken\", color=red]",
+                    EdgeKind::Fallthrough => "",
+                };
+
+                writeln!(out, "    b{} -> {}{};", block.start, to, attrs).unwrap();
+            }
+        }
+
+        for cell in indirect_cells {
+            writeln!(
+                out,
+                "    ind{} [label=\"jump to [{}]\", shape=ellipse, style=dashed];",
+                cell, cell
+            )
+            .unwrap();
+        }
+
+        writeln!(out, "}}").unwrap();
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::io::QueueIO;
+    use crate::intcode::{CPUExceptionKind, IntcodeCPU};
+
+    #[test]
+    fn indirect_jump_splits_blocks() {
+        let image = [3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9];
+        let cfg = ControlFlowGraph::build(&image);
+
+        let starts = cfg.blocks().map(BasicBlock::start).collect::<Vec<usize>>();
+        assert_eq!(starts, vec![0, 5]);
+
+        let entry = cfg.block_at(0).unwrap();
+        assert_eq!(entry.end(), 5);
+        assert_eq!(
+            entry.successors(),
+            &[
+                Edge {
+                    kind: EdgeKind::Taken,
+                    target: Target::Indirect(15),
+                },
+                Edge {
+                    kind: EdgeKind::NotTaken,
+                    target: Target::Direct(5),
+                },
+            ][..]
+        );
+
+        let exit = cfg.block_at(5).unwrap();
+        assert_eq!(
+            exit.instruction_addresses().collect::<Vec<usize>>(),
+            vec![5, 9, 11]
+        );
+        assert!(exit.successors().is_empty());
+    }
+
+    #[test]
+    fn constant_conditions_only_follow_one_edge() {
+        let image = [1105, 1, 4, 99, 1106, 0, 8, 99, 99];
+        let cfg = ControlFlowGraph::build(&image);
+
+        let starts = cfg.blocks().map(BasicBlock::start).collect::<Vec<usize>>();
+        assert_eq!(starts, vec![0, 4, 8]);
+        assert!(cfg.block_at(3).is_none());
+    }
+
+    #[test]
+    fn undecodable_target_is_a_fault() {
+        let image = [1105, 1, 50];
+        let cfg = ControlFlowGraph::build(&image);
+
+        let target = cfg.block_at(50).unwrap();
+        assert_eq!(target.fault().map(|(addr, _)| *addr), Some(50));
+    }
+
+    #[test]
+    fn undefined_opcode_is_a_fault() {
+        let image = [104, 1, 42, 99];
+        let cfg = ControlFlowGraph::build(&image);
+
+        let entry = cfg.block_at(0).unwrap();
+        assert_eq!(entry.end(), 2);
+        assert!(entry.successors().is_empty());
+        let (addr, ex) = entry.fault().unwrap();
+        assert_eq!((*addr, ex.kind()), (2, CPUExceptionKind::InvalidOpcode));
+        assert_eq!(
+            cfg.disassembly(&image),
+            "     0  OUTPUT 1\n     2  DATA 42\n     3  DATA 99\n"
+        );
+    }
+
+    #[test]
+    fn coverage_follows_patched_code() {
+        // Patches [4] from 0 into OUTPUT before running it
+        let image = vec![1101, 4, 0, 4, 0, 7, 99, 42];
+        assert!(ControlFlowGraph::build(&image)
+            .block_at(0)
+            .unwrap()
+            .fault()
+            .is_some());
+
+        let mut cpu = IntcodeCPU::with_io(image, QueueIO::default());
+        cpu.enable_coverage();
+        cpu.run().expect("Should not have excepted at runtime");
+        let cfg = ControlFlowGraph::from_coverage(&cpu.inspect_state(), cpu.coverage().unwrap());
+
+        let starts = cfg.blocks().map(BasicBlock::start).collect::<Vec<usize>>();
+        assert_eq!(starts, vec![0]);
+        let entry = cfg.block_at(0).unwrap();
+        assert!(entry.fault().is_none());
+        assert_eq!(
+            entry.instruction_addresses().collect::<Vec<usize>>(),
+            vec![0, 4, 6]
+        );
+    }
+
+    #[test]
+    fn dot_export_contains_blocks_and_edges() {
+        let image = [3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9];
+        let dot = ControlFlowGraph::build(&image).to_dot();
+
+        assert!(dot.starts_with("digraph cfg {"));
+        assert!(dot.contains("b0 -> ind15"));
+        assert!(dot.contains("b0 -> b5"));
+        assert!(dot.contains("5: ADD [13], [14] -> [13]"));
+    }
+
+    #[test]
+    fn disassembly_separates_code_from_data() {
+        let image = [1105, 1, 4, 7, 104, 3, 99];
+        let listing = ControlFlowGraph::build(&image).disassembly(&image);
+
+        assert_eq!(
+            listing,
+            "     0  JNZ 1, 4\n     3  DATA 7\n     4  OUTPUT 3\n     6  HALT\n"
+        );
+    }
+}
@@ -0,0 +1,302 @@
+use std::fmt;
+
+use super::engine::Engine;
+use super::io::QueueIO;
+use super::{CPUExceptionKind, CPUState};
+
+/// A program with its inputs and the behaviour every engine must show
+pub struct Case {
+    pub name: &'static str,
+    pub program: &'static [i32],
+    pub inputs: &'static [i32],
+    pub outputs: &'static [i32],
+    /// Memory once the program has halted, if the case checks it
+    pub memory: Option<&'static [i32]>,
+    /// Exception the program must raise instead of halting
+    pub exception: Option<CPUExceptionKind>,
+}
+
+const fn outputs(
+    name: &'static str,
+    program: &'static [i32],
+    inputs: &'static [i32],
+    outputs: &'static [i32],
+) -> Case {
+    Case {
+        name,
+        program,
+        inputs,
+        outputs,
+        memory: None,
+        exception: None,
+    }
+}
+
+const fn memory(name: &'static str, program: &'static [i32], memory: &'static [i32]) -> Case {
+    Case {
+        name,
+        program,
+        inputs: &[],
+        outputs: &[],
+        memory: Some(memory),
+        exception: None,
+    }
+}
+
+const fn raises(name: &'static str, program: &'static [i32], kind: CPUExceptionKind) -> Case {
+    Case {
+        name,
+        program,
+        inputs: &[],
+        outputs: &[],
+        memory: None,
+        exception: Some(kind),
+    }
+}
+
+const EQUAL_TO_8_POSITION: &[i32] = &[3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8];
+const LESS_THAN_8_POSITION: &[i32] = &[3, 9, 7, 9, 10, 9, 4, 9, 99, -1, 8];
+const EQUAL_TO_8_IMMEDIATE: &[i32] = &[3, 3, 1108, -1, 8, 3, 4, 3, 99];
+const LESS_THAN_8_IMMEDIATE: &[i32] = &[3, 3, 1107, -1, 8, 3, 4, 3, 99];
+const JUMP_POSITION: &[i32] = &[3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9];
+const JUMP_IMMEDIATE: &[i32] = &[3, 3, 1105, -1, 9, 1101, 0, 0, 12, 4, 12, 99, 1];
+const AROUND_8: &[i32] = &[
+    3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36, 98, 0, 0,
+    1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000, 1, 20, 4, 20, 1105,
+    1, 46, 98, 99,
+];
+
+/// Every opcode in every parameter mode it accepts, the published day 2 and
+/// day 5 examples, and the exceptions raised for malformed programs.
+pub const CASES: &[Case] = &[
+    memory("halt", &[99], &[99]),
+    memory("add position", &[1, 0, 0, 0, 99], &[2, 0, 0, 0, 99]),
+    memory(
+        "add immediate",
+        &[1101, 100, -1, 4, 0],
+        &[1101, 100, -1, 4, 99],
+    ),
+    memory("mul position", &[2, 3, 0, 3, 99], &[2, 3, 0, 6, 99]),
+    memory("mul immediate", &[1002, 4, 3, 4, 33], &[1002, 4, 3, 4, 99]),
+    memory(
+        "day 2 example",
+        &[1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50],
+        &[3500, 9, 10, 70, 2, 3, 11, 0, 99, 30, 40, 50],
+    ),
+    memory(
+        "day 2 self-modifying example",
+        &[1, 1, 1, 4, 99, 5, 6, 0, 99],
+        &[30, 1, 1, 4, 2, 5, 6, 0, 99],
+    ),
+    outputs("echo", &[3, 0, 4, 0, 99], &[42], &[42]),
+    Case {
+        name: "input writes to its first parameter",
+        program: &[3, 6, 104, 1, 99, 0, 0],
+        inputs: &[-7],
+        outputs: &[1],
+        memory: Some(&[3, 6, 104, 1, 99, 0, -7]),
+        exception: None,
+    },
+    outputs("output position", &[4, 3, 99, 17], &[], &[17]),
+    outputs("output immediate", &[104, -3, 99], &[], &[-3]),
+    outputs(
+        "equal to 8 position, equal",
+        EQUAL_TO_8_POSITION,
+        &[8],
+        &[1],
+    ),
+    outputs(
+        "equal to 8 position, not equal",
+        EQUAL_TO_8_POSITION,
+        &[7],
+        &[0],
+    ),
+    outputs(
+        "less than 8 position, less",
+        LESS_THAN_8_POSITION,
+        &[5],
+        &[1],
+    ),
+    outputs(
+        "less than 8 position, not less",
+        LESS_THAN_8_POSITION,
+        &[8],
+        &[0],
+    ),
+    outputs(
+        "equal to 8 immediate, equal",
+        EQUAL_TO_8_IMMEDIATE,
+        &[8],
+        &[1],
+    ),
+    outputs(
+        "equal to 8 immediate, not equal",
+        EQUAL_TO_8_IMMEDIATE,
+        &[9],
+        &[0],
+    ),
+    outputs(
+        "less than 8 immediate, less",
+        LESS_THAN_8_IMMEDIATE,
+        &[-4],
+        &[1],
+    ),
+    outputs(
+        "less than 8 immediate, not less",
+        LESS_THAN_8_IMMEDIATE,
+        &[8],
+        &[0],
+    ),
+    outputs("jump position, zero", JUMP_POSITION, &[0], &[0]),
+    outputs("jump position, non-zero", JUMP_POSITION, &[5], &[1]),
+    outputs("jump immediate, zero", JUMP_IMMEDIATE, &[0], &[0]),
+    outputs("jump immediate, non-zero", JUMP_IMMEDIATE, &[5], &[1]),
+    outputs("around 8, below", AROUND_8, &[7], &[999]),
+    outputs("around 8, equal", AROUND_8, &[8], &[1000]),
+    outputs("around 8, above", AROUND_8, &[9], &[1001]),
+    raises("undefined opcode", &[42], CPUExceptionKind::InvalidOpcode),
+    raises("negative opcode", &[-1], CPUExceptionKind::InvalidOpcode),
+    raises(
+        "undefined mode",
+        &[201, 0, 0, 0, 99],
+        CPUExceptionKind::InvalidOperand,
+    ),
+    raises(
+        "immediate destination",
+        &[11101, 1, 1, 0, 99],
+        CPUExceptionKind::InvalidOperand,
+    ),
+    raises(
+        "read out of bounds",
+        &[4, 10, 99],
+        CPUExceptionKind::OutOfBounds,
+    ),
+    raises(
+        "write out of bounds",
+        &[1101, 1, 1, 10, 99],
+        CPUExceptionKind::OutOfBounds,
+    ),
+    raises(
+        "truncated instruction",
+        &[1, 0, 0],
+        CPUExceptionKind::OutOfBounds,
+    ),
+    raises("run off the end", &[104, 1], CPUExceptionKind::OutOfBounds),
+    raises(
+        "add overflow",
+        &[1101, i32::MAX, 1, 0, 99],
+        CPUExceptionKind::Overflow,
+    ),
+    raises(
+        "mul overflow",
+        &[1102, i32::MIN, -1, 0, 99],
+        CPUExceptionKind::Overflow,
+    ),
+];
+
+/// A case on which an engine behaved differently to what was expected
+#[derive(Clone, Debug)]
+pub struct Mismatch {
+    pub case: &'static str,
+    pub message: String,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.case, self.message)
+    }
+}
+
+impl Case {
+    /// Runs the case on an engine built by `make` from its program and a
+    /// queue holding its inputs
+    pub fn check<E, F>(&self, make: F) -> Result<(), Mismatch>
+    where
+        E: Engine<IO = QueueIO>,
+        F: FnOnce(Vec<i32>, QueueIO) -> E,
+    {
+        let mismatch = |message: String| Mismatch {
+            case: self.name,
+            message,
+        };
+
+        let mut engine = make(self.program.to_vec(), QueueIO::new(self.inputs.to_vec()));
+
+        match (engine.run(), self.exception) {
+            (Ok(CPUState::Halted), None) => {}
+            (Ok(state), None) => {
+                return Err(mismatch(format!(
+                    "expected to halt, stopped {:?} at {}",
+                    state,
+                    engine.pc()
+                )))
+            }
+            (Ok(_), Some(kind)) => return Err(mismatch(format!("expected {:?}, got none", kind))),
+            (Err(ex), None) => return Err(mismatch(format!("unexpected exception {}", ex))),
+            (Err(ex), Some(kind)) if ex.kind() != kind => {
+                return Err(mismatch(format!("expected {:?}, got {}", kind, ex)))
+            }
+            (Err(_), Some(_)) => return Ok(()),
+        }
+
+        if engine.io().outputs() != self.outputs {
+            return Err(mismatch(format!(
+                "expected outputs {:?}, got {:?}",
+                self.outputs,
+                engine.io().outputs()
+            )));
+        }
+
+        if let Some(memory) = self.memory {
+            if !engine.memory().iter().eq(memory.iter().copied()) {
+                return Err(mismatch(format!(
+                    "expected memory {:?}, got {:?}",
+                    memory,
+                    engine.memory()
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Runs every case in `CASES`, returning those the engine got wrong
+pub fn check_all<E, F>(make: F) -> Vec<Mismatch>
+where
+    E: Engine<IO = QueueIO>,
+    F: Fn(Vec<i32>, QueueIO) -> E,
+{
+    CASES
+        .iter()
+        .filter_map(|case| case.check(&make).err())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::IntcodeCPU;
+
+    #[test]
+    fn shared_cpu_conforms() {
+        let mismatches = check_all(IntcodeCPU::with_io);
+
+        for mismatch in &mismatches {
+            eprintln!("{}", mismatch);
+        }
+        assert!(mismatches.is_empty());
+    }
+
+    #[test]
+    fn lenient_cpu_differs_only_on_immediate_destination() {
+        let mismatches = check_all(|program, io| {
+            let mut cpu = IntcodeCPU::with_io(program, io);
+            cpu.set_lenient_operands(true);
+            cpu
+        });
+
+        let names = mismatches.iter().map(|m| m.case).collect::<Vec<_>>();
+        assert_eq!(names, vec!["immediate destination"]);
+    }
+}
@@ -0,0 +1,239 @@
+use std::fmt::Write;
+
+use super::CPUOp;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CellUsage {
+    /// Number of times an instruction starting at this cell was executed
+    pub executed: u64,
+    /// Number of times this cell was fetched as an instruction parameter
+    pub fetched: u64,
+    /// Number of times this cell was read as a position-mode operand
+    pub reads: u64,
+    pub writes: u64,
+}
+
+impl CellUsage {
+    pub fn is_code(&self) -> bool {
+        self.executed > 0 || self.fetched > 0
+    }
+
+    pub fn is_touched(&self) -> bool {
+        self.is_code() || self.reads > 0 || self.writes > 0
+    }
+
+    /// Code cells that the program also wrote to
+    pub fn is_self_modified(&self) -> bool {
+        self.is_code() && self.writes > 0
+    }
+
+    /// Single character summary used by the heatmap
+    fn symbol(&self) -> char {
+        if self.is_self_modified() {
+            '!'
+        } else if self.is_code() {
+            'x'
+        } else if self.writes > 0 {
+            'w'
+        } else if self.reads > 0 {
+            'r'
+        } else {
+            '.'
+        }
+    }
+
+    fn flags(&self) -> String {
+        let flag = |set: bool, c: char| if set { c } else { '-' };
+
+        [
+            flag(self.executed > 0, 'X'),
+            flag(self.reads > 0, 'R'),
+            flag(self.writes > 0, 'W'),
+        ]
+        .iter()
+        .collect()
+    }
+}
+
+/// Per-cell record of how memory was used during a run.
+#[derive(Clone, Debug)]
+pub struct Coverage {
+    cells: Vec<CellUsage>,
+    /// What each code cell held the first time it was executed or fetched
+    code: Vec<Option<i32>>,
+}
+
+impl Coverage {
+    pub fn new(len: usize) -> Self {
+        Coverage {
+            cells: vec![CellUsage::default(); len],
+            code: vec![None; len],
+        }
+    }
+
+    /// Records the instruction at `pc`, whose cells held `code` when it was
+    /// fetched
+    pub(super) fn record_execute(&mut self, pc: usize, code: &[i32]) {
+        if let Some(cell) = self.cells.get_mut(pc) {
+            cell.executed += 1;
+        }
+        for cell in self
+            .cells
+            .iter_mut()
+            .skip(pc + 1)
+            .take(code.len().saturating_sub(1))
+        {
+            cell.fetched += 1;
+        }
+        for (cell, &val) in self.code.iter_mut().skip(pc).zip(code) {
+            cell.get_or_insert(val);
+        }
+    }
+
+    pub(super) fn record_read(&mut self, pos: usize) {
+        if let Some(cell) = self.cells.get_mut(pos) {
+            cell.reads += 1;
+        }
+    }
+
+    pub(super) fn record_write(&mut self, pos: usize) {
+        if let Some(cell) = self.cells.get_mut(pos) {
+            cell.writes += 1;
+        }
+    }
+
+    pub fn cell(&self, pos: usize) -> Option<&CellUsage> {
+        self.cells.get(pos)
+    }
+
+    /// Addresses at which an instruction was executed
+    pub fn executed_addresses(&self) -> impl Iterator<Item = usize> + '_ {
+        self.cells
+            .iter()
+            .enumerate()
+            .filter(|(_, cell)| cell.executed > 0)
+            .map(|(pos, _)| pos)
+    }
+
+    /// `image` with every code cell put back to what it held when it was
+    /// first run, undoing the program overwriting its code afterwards
+    pub fn executed_image(&self, image: &[i32]) -> Vec<i32> {
+        image
+            .iter()
+            .enumerate()
+            .map(|(pos, &val)| self.code.get(pos).copied().flatten().unwrap_or(val))
+            .collect()
+    }
+
+    pub fn self_modified_addresses(&self) -> impl Iterator<Item = usize> + '_ {
+        self.cells
+            .iter()
+            .enumerate()
+            .filter(|(_, cell)| cell.is_self_modified())
+            .map(|(pos, _)| pos)
+    }
+
+    /// Renders one character per cell, `width` cells per row:
+    /// `x` code, `!` self-modified code, `w` written data, `r` read-only
+    /// data and `.` untouched.
+    pub fn heatmap(&self, width: usize) -> String {
+        let mut out = String::new();
+
+        for (row, cells) in self.cells.chunks(width.max(1)).enumerate() {
+            let line = cells.iter().map(CellUsage::symbol).collect::<String>();
+            writeln!(out, "{:>6}  {}", row * width.max(1), line).unwrap();
+        }
+
+        out
+    }
+
+    /// Lists `image` as disassembly where an instruction was executed and as
+    /// raw data everywhere else, flagging each line with X (executed),
+    /// R (read) and W (written).
+    pub fn annotated_disassembly(&self, image: &[i32]) -> String {
+        let mut out = String::new();
+        let mut pos = 0;
+
+        while pos < image.len() {
+            let usage = self.cells.get(pos).copied().unwrap_or_default();
+            let decoded = if usage.executed > 0 {
+                CPUOp::decode(pos, |addr| image.get(addr).copied(), true).ok()
+            } else {
+                None
+            };
+
+            match decoded {
+                Some(op) => {
+                    let len = op.next_pc_offset().max(1);
+                    let modified = (pos..pos + len)
+                        .filter_map(|addr| self.cells.get(addr))
+                        .any(|cell| cell.writes > 0);
+
+                    writeln!(
+                        out,
+                        "{:>6}  {}  {:<28} x{}{}",
+                        pos,
+                        usage.flags(),
+                        op.to_string(),
+                        usage.executed,
+                        if modified { "  (self-modified)" } else { "" }
+                    )
+                    .unwrap();
+                    pos += len;
+                }
+                None => {
+                    writeln!(out, "{:>6}  {}  DATA {}", pos, usage.flags(), image[pos]).unwrap();
+                    pos += 1;
+                }
+            }
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::intcode::IntcodeCPU;
+
+    #[test]
+    fn records_code_and_data_cells() {
+        let mut cpu = IntcodeCPU::new(vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]);
+        cpu.enable_coverage();
+        cpu.run().expect("Should not have excepted at runtime");
+
+        let coverage = cpu.coverage().unwrap();
+
+        assert_eq!(
+            coverage.executed_addresses().collect::<Vec<usize>>(),
+            vec![0, 4, 8]
+        );
+        assert_eq!(coverage.cell(9).unwrap().reads, 1);
+        assert_eq!(coverage.cell(0).unwrap().writes, 1);
+        assert_eq!(
+            coverage.self_modified_addresses().collect::<Vec<usize>>(),
+            vec![0, 3]
+        );
+        assert!(!coverage.cell(11).unwrap().is_code());
+        // [0] and [3] were only overwritten after they ran
+        assert_eq!(
+            coverage.executed_image(&cpu.inspect_state()),
+            vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]
+        );
+    }
+
+    #[test]
+    fn renders_heatmap_and_disassembly() {
+        let mut cpu = IntcodeCPU::new(vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]);
+        cpu.enable_coverage();
+        cpu.run().expect("Should not have excepted at runtime");
+
+        let coverage = cpu.coverage().unwrap();
+
+        assert_eq!(coverage.heatmap(6), "     0  !xx!xx\n     6  xxxrrr\n");
+
+        let listing = coverage.annotated_disassembly(&cpu.inspect_state());
+        assert!(listing.contains("     4  X--  MUL [3], [11] -> [0]"));
+        assert!(listing.contains("    11  -R-  DATA 50"));
+    }
+}
@@ -0,0 +1,225 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use super::CPUResult;
+
+/// Something mapped into a range of memory addresses. Reads and writes
+/// within the range are passed to the device instead of touching memory.
+///
+/// `offset` is relative to the start of the range the device is attached
+/// at, and `now` is the number of instructions the CPU has executed.
+pub trait Device {
+    fn read(&mut self, offset: usize, now: u64) -> CPUResult<i32>;
+
+    fn write(&mut self, offset: usize, value: i32, now: u64) -> CPUResult<()>;
+
+    /// A copy in the current state, for a forked CPU to use instead
+    fn fork(&self) -> SharedDevice;
+}
+
+/// A device shared between the CPU and whoever attached it
+pub type SharedDevice = Rc<RefCell<dyn Device>>;
+
+/// A character console. Writes are collected as output and reads return
+/// queued input, or -1 once it runs out.
+#[derive(Clone, Debug, Default)]
+pub struct Console {
+    input: VecDeque<i32>,
+    output: Vec<i32>,
+}
+
+impl Console {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push_input(&mut self, value: i32) {
+        self.input.push_back(value);
+    }
+
+    /// Queue the characters of `text` as input
+    pub fn push_str(&mut self, text: &str) {
+        self.input.extend(text.chars().map(|c| c as i32));
+    }
+
+    pub fn output(&self) -> &[i32] {
+        &self.output
+    }
+
+    /// Output so far as text, with values that aren't characters dropped
+    pub fn output_text(&self) -> String {
+        self.output
+            .iter()
+            .filter_map(|&val| std::char::from_u32(val as u32))
+            .collect()
+    }
+}
+
+impl Device for Console {
+    fn read(&mut self, _offset: usize, _now: u64) -> CPUResult<i32> {
+        Ok(self.input.pop_front().unwrap_or(-1))
+    }
+
+    fn write(&mut self, _offset: usize, value: i32, _now: u64) -> CPUResult<()> {
+        self.output.push(value);
+        Ok(())
+    }
+
+    fn fork(&self) -> SharedDevice {
+        Rc::new(RefCell::new(self.clone()))
+    }
+}
+
+/// Reads give the number of instructions executed since the timer was last
+/// written to (or since the CPU started). Any write restarts it.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Timer {
+    start: u64,
+}
+
+impl Timer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Device for Timer {
+    fn read(&mut self, _offset: usize, now: u64) -> CPUResult<i32> {
+        // A CPU forked from before the timer was restarted by a CPU sharing
+        // it can be behind the start
+        Ok(now.saturating_sub(self.start).min(i32::MAX as u64) as i32)
+    }
+
+    fn write(&mut self, _offset: usize, _value: i32, now: u64) -> CPUResult<()> {
+        self.start = now;
+        Ok(())
+    }
+
+    fn fork(&self) -> SharedDevice {
+        Rc::new(RefCell::new(*self))
+    }
+}
+
+/// Reads give non-negative pseudo-random numbers from a xorshift generator.
+/// Writes reseed it, so programs can make their runs repeatable.
+#[derive(Copy, Clone, Debug)]
+pub struct Random {
+    state: u64,
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        // xorshift never leaves the all-zero state
+        Random { state: seed.max(1) }
+    }
+}
+
+impl Device for Random {
+    fn read(&mut self, _offset: usize, _now: u64) -> CPUResult<i32> {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        Ok((self.state >> 33) as i32)
+    }
+
+    fn write(&mut self, _offset: usize, value: i32, _now: u64) -> CPUResult<()> {
+        *self = Random::new(u64::from(value as u32));
+        Ok(())
+    }
+
+    fn fork(&self) -> SharedDevice {
+        Rc::new(RefCell::new(*self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::io::QueueIO;
+    use crate::intcode::IntcodeCPU;
+
+    #[test]
+    fn console_echoes_through_mapped_address() {
+        // Copy two characters from the console back to it. Address 100 is
+        // past the end of memory, so is only reachable through the mapping
+        let program = vec![1, 100, 9, 100, 1, 100, 9, 100, 99, 0];
+        let console = Rc::new(RefCell::new(Console::new()));
+        console.borrow_mut().push_str("Hi");
+
+        let mut cpu = IntcodeCPU::with_io(program, QueueIO::default());
+        cpu.attach_device(100..101, console.clone());
+        cpu.run().expect("Should not have excepted at runtime");
+
+        assert_eq!(console.borrow().output_text(), "Hi");
+        assert_eq!(cpu.inspect_state().len(), 10);
+    }
+
+    #[test]
+    fn timer_and_random_are_readable() {
+        // After one instruction, [17] = timer and [18] = random, then
+        // output both
+        let program = vec![
+            1101, 0, 0, 18, 1, 50, 19, 17, 1, 60, 19, 18, 4, 17, 4, 18, 99, 0, 0, 0,
+        ];
+        let mut cpu = IntcodeCPU::with_io(program, QueueIO::default());
+        cpu.attach_device(50..51, Rc::new(RefCell::new(Timer::new())));
+        cpu.attach_device(60..61, Rc::new(RefCell::new(Random::new(7))));
+        cpu.run().expect("Should not have excepted at runtime");
+
+        // A small seed's first value is too small to survive the shift
+        assert_eq!(cpu.io().outputs(), &[1, 0]);
+    }
+
+    #[test]
+    fn random_is_repeatable_for_a_seed() {
+        // Output three values, reseed with 7 and output three more
+        let program = vec![4, 60, 4, 60, 4, 60, 1101, 0, 7, 60, 4, 60, 4, 60, 4, 60, 99];
+        let run = || {
+            let mut cpu = IntcodeCPU::with_io(program.clone(), QueueIO::default());
+            cpu.attach_device(60..61, Rc::new(RefCell::new(Random::new(7))));
+            cpu.run().expect("Should not have excepted at runtime");
+            cpu.io().outputs().to_vec()
+        };
+
+        let expected = [0, 939_582_345, 1_621_888_992];
+        let outputs = run();
+        assert_eq!(outputs[..3], expected);
+        assert_eq!(outputs[3..], expected);
+        assert_eq!(run(), outputs);
+    }
+
+    #[test]
+    fn clones_behind_a_restarted_timer_read_zero() {
+        // If [17] is set, waste a step and restart the timer first. Either
+        // way, output it
+        let program = vec![
+            1005, 17, 6, 4, 50, 99, 1101, 0, 0, 18, 1101, 0, 0, 50, 1105, 1, 3, 0, 0,
+        ];
+        let mut ahead = IntcodeCPU::with_io(program, QueueIO::default());
+        ahead.attach_device(50..51, Rc::new(RefCell::new(Timer::new())));
+        let mut behind = ahead.clone();
+        ahead.set_position(17, 1);
+        ahead.run().expect("Should not have excepted at runtime");
+        behind.run().expect("Should not have excepted at runtime");
+
+        assert_eq!(ahead.io().outputs(), &[2]);
+        assert_eq!(behind.io().outputs(), &[0]);
+    }
+
+    #[test]
+    fn forks_have_their_own_devices() {
+        let program = vec![1, 100, 5, 100, 99, 0];
+        let console = Rc::new(RefCell::new(Console::new()));
+        console.borrow_mut().push_str("ab");
+
+        let mut cpu = IntcodeCPU::with_io(program, QueueIO::default());
+        cpu.attach_device(100..101, console.clone());
+        let mut fork = cpu.fork();
+        fork.run().expect("Should not have excepted at runtime");
+        cpu.run().expect("Should not have excepted at runtime");
+
+        // Both read the first character; only the original is visible here
+        assert_eq!(console.borrow().output_text(), "a");
+    }
+}
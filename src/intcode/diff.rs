@@ -0,0 +1,117 @@
+use std::fmt::Write;
+use std::ops::Range;
+
+use super::cfg::ControlFlowGraph;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Change {
+    pub pos: usize,
+    pub old: i32,
+    pub new: i32,
+}
+
+/// Every cell whose value differs between two memory images
+#[derive(Clone, Debug, Default)]
+pub struct MemoryDiff {
+    changes: Vec<Change>,
+}
+
+impl MemoryDiff {
+    /// Compares the cells the two images have in common
+    pub fn between(before: &[i32], after: &[i32]) -> Self {
+        let changes = before
+            .iter()
+            .zip(after)
+            .enumerate()
+            .filter(|(_, (old, new))| old != new)
+            .map(|(pos, (&old, &new))| Change { pos, old, new })
+            .collect();
+
+        MemoryDiff { changes }
+    }
+
+    pub fn changes(&self) -> &[Change] {
+        &self.changes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Changed addresses grouped into runs of consecutive cells
+    pub fn ranges(&self) -> Vec<Range<usize>> {
+        let mut ranges: Vec<Range<usize>> = vec![];
+
+        for change in &self.changes {
+            match ranges.last_mut() {
+                Some(range) if range.end == change.pos => range.end += 1,
+                _ => ranges.push(change.pos..change.pos + 1),
+            }
+        }
+
+        ranges
+    }
+
+    /// Lists the changes range by range. Given the control flow graph of
+    /// the initial image, changed cells that belonged to an instruction are
+    /// annotated with it.
+    pub fn report(&self, cfg: Option<&ControlFlowGraph>) -> String {
+        let mut out = String::new();
+        let mut changes = self.changes.iter();
+
+        for range in self.ranges() {
+            writeln!(out, "[{}..{}]", range.start, range.end).unwrap();
+
+            for change in changes.by_ref().take(range.len()) {
+                write!(out, "{:>6}: {} -> {}", change.pos, change.old, change.new).unwrap();
+                if let Some((addr, op)) = cfg.and_then(|cfg| cfg.instruction_containing(change.pos))
+                {
+                    write!(out, "    ; in {}: {}", addr, op).unwrap();
+                }
+                writeln!(out).unwrap();
+            }
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::io::QueueIO;
+    use crate::intcode::IntcodeCPU;
+
+    #[test]
+    fn groups_changes_into_ranges() {
+        let before = [1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50];
+        let mut cpu = IntcodeCPU::with_io(before.to_vec(), QueueIO::default());
+        cpu.run().expect("Should not have excepted at runtime");
+
+        let diff = MemoryDiff::between(&before, &cpu.inspect_state());
+
+        assert_eq!(
+            diff.changes(),
+            &[
+                Change {
+                    pos: 0,
+                    old: 1,
+                    new: 3500
+                },
+                Change {
+                    pos: 3,
+                    old: 3,
+                    new: 70
+                },
+            ]
+        );
+        assert_eq!(diff.ranges(), vec![0..1, 3..4]);
+
+        let cfg = ControlFlowGraph::build(&before);
+        assert_eq!(
+            diff.report(Some(&cfg)),
+            "[0..1]\n     0: 1 -> 3500    ; in 0: ADD [9], [10] -> [3]\n\
+             [3..4]\n     3: 3 -> 70    ; in 0: ADD [9], [10] -> [3]\n"
+        );
+    }
+}
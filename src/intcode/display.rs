@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use super::io::IntcodeIO;
+use super::CPUResult;
+
+/// Moves the cursor home and clears the terminal
+const CLEAR_SCREEN: &str = "\x1b[H\x1b[2J";
+
+/// Characters to draw each tile id with
+#[derive(Clone, Debug)]
+pub struct Palette {
+    chars: HashMap<i32, char>,
+    unknown: char,
+}
+
+impl Palette {
+    /// A palette with no tiles, drawing every id as `?`
+    pub fn new() -> Self {
+        Palette {
+            chars: HashMap::new(),
+            unknown: '?',
+        }
+    }
+
+    /// Empty, wall, block, paddle and ball, as used by the arcade cabinet
+    pub fn arcade() -> Self {
+        Palette::new()
+            .tile(0, ' ')
+            .tile(1, '#')
+            .tile(2, '=')
+            .tile(3, '-')
+            .tile(4, 'o')
+    }
+
+    pub fn tile(mut self, id: i32, c: char) -> Self {
+        self.chars.insert(id, c);
+        self
+    }
+
+    /// The character for ids the palette doesn't list
+    pub fn unknown(mut self, c: char) -> Self {
+        self.unknown = c;
+        self
+    }
+
+    pub fn char_for(&self, id: i32) -> char {
+        self.chars.get(&id).copied().unwrap_or(self.unknown)
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::new()
+    }
+}
+
+/// Interprets outputs as `(x, y, tile)` triples drawing on a sparse grid,
+/// except that a triple at the score position sets the score instead.
+///
+/// Used as the CPU's I/O, the screen ends a frame whenever the program asks
+/// for input, which it never supplies. Each frame can be drawn on the
+/// terminal and written to a numbered file.
+#[derive(Clone, Debug, Default)]
+pub struct Screen {
+    tiles: HashMap<(i32, i32), i32>,
+    pending: Vec<i32>,
+    palette: Palette,
+    score_at: Option<(i32, i32)>,
+    score: Option<i32>,
+    frames: usize,
+    live: bool,
+    frame_dir: Option<PathBuf>,
+    error: Option<String>,
+}
+
+impl Screen {
+    pub fn new(palette: Palette) -> Self {
+        Screen {
+            palette,
+            score_at: Some((-1, 0)),
+            ..Self::default()
+        }
+    }
+
+    /// Where triples set the score rather than a tile, `(-1, 0)` by
+    /// default. `None` treats every triple as a tile.
+    pub fn score_at(mut self, pos: Option<(i32, i32)>) -> Self {
+        self.score_at = pos;
+        self
+    }
+
+    /// Redraw the terminal at the end of every frame
+    pub fn live(mut self) -> Self {
+        self.live = true;
+        self
+    }
+
+    /// Write every frame to `dir/frame-NNNNN.txt`
+    pub fn frame_dir<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.frame_dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Applies one output value, drawing once a triple is complete
+    pub fn push(&mut self, value: i32) {
+        self.pending.push(value);
+        if let [x, y, tile] = self.pending[..] {
+            self.pending.clear();
+
+            if Some((x, y)) == self.score_at {
+                self.score = Some(tile);
+            } else {
+                self.tiles.insert((x, y), tile);
+            }
+        }
+    }
+
+    pub fn tile(&self, x: i32, y: i32) -> Option<i32> {
+        self.tiles.get(&(x, y)).copied()
+    }
+
+    /// Every drawn cell and its tile, in no particular order
+    pub fn tiles(&self) -> impl Iterator<Item = ((i32, i32), i32)> + '_ {
+        self.tiles.iter().map(|(&pos, &tile)| (pos, tile))
+    }
+
+    /// Number of cells currently showing `tile`
+    pub fn count(&self, tile: i32) -> usize {
+        self.tiles.values().filter(|&&t| t == tile).count()
+    }
+
+    /// Some cell showing `tile`, e.g. the position of the ball
+    pub fn find(&self, tile: i32) -> Option<(i32, i32)> {
+        self.tiles().find(|&(_, t)| t == tile).map(|(pos, _)| pos)
+    }
+
+    /// The last value written to the score register
+    pub fn score(&self) -> Option<i32> {
+        self.score
+    }
+
+    /// Number of frames ended so far
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    /// The grid bounded by the cells drawn so far, top row first, followed
+    /// by the score if one has been set
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        if let Some((min_x, max_x, min_y, max_y)) = self.bounds() {
+            for y in min_y..=max_y {
+                let row = (min_x..=max_x)
+                    .map(|x| match self.tile(x, y) {
+                        Some(tile) => self.palette.char_for(tile),
+                        None => ' ',
+                    })
+                    .collect::<String>();
+                out.push_str(row.trim_end());
+                out.push('\n');
+            }
+        }
+
+        if let Some(score) = self.score {
+            out.push_str(&format!("Score: {}\n", score));
+        }
+
+        out
+    }
+
+    fn bounds(&self) -> Option<(i32, i32, i32, i32)> {
+        let xs = self.tiles.keys().map(|&(x, _)| x);
+        let ys = self.tiles.keys().map(|&(_, y)| y);
+
+        Some((xs.clone().min()?, xs.max()?, ys.clone().min()?, ys.max()?))
+    }
+
+    /// Ends the current frame, drawing it on the terminal and writing it to
+    /// the frame directory as configured
+    pub fn end_frame(&mut self) -> io::Result<()> {
+        self.frames += 1;
+        let frame = self.render();
+
+        if self.live {
+            print!("{}{}", CLEAR_SCREEN, frame);
+            io::stdout().flush()?;
+        }
+
+        if let Some(dir) = &self.frame_dir {
+            fs::create_dir_all(dir)?;
+            fs::write(dir.join(format!("frame-{:05}.txt", self.frames)), frame)?;
+        }
+
+        Ok(())
+    }
+
+    /// Ends a frame on behalf of the CPU, which has no way to hear about
+    /// I/O errors. The first one stops further frames being written.
+    pub(super) fn end_frame_quietly(&mut self) {
+        if self.error.is_some() {
+            self.frames += 1;
+            return;
+        }
+
+        if let Err(e) = self.end_frame() {
+            self.error = Some(e.to_string());
+        }
+    }
+
+    /// Why frames stopped being drawn or written, if they did
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+}
+
+impl IntcodeIO for Screen {
+    fn input(&mut self) -> CPUResult<Option<i32>> {
+        self.end_frame_quietly();
+        Ok(None)
+    }
+
+    fn output(&mut self, value: i32) {
+        self.push(value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{CPUState, IntcodeCPU};
+
+    #[test]
+    fn triples_draw_tiles_and_set_score() {
+        let mut screen = Screen::new(Palette::arcade());
+        for &val in &[1, 2, 3, 6, 5, 4, -1, 0, 12345, 0, 0, 1, 0, 2, 2] {
+            screen.push(val);
+        }
+
+        assert_eq!(screen.tile(1, 2), Some(3));
+        assert_eq!(screen.tile(6, 5), Some(4));
+        assert_eq!(screen.count(1), 1);
+        assert_eq!(screen.find(4), Some((6, 5)));
+        assert_eq!(screen.score(), Some(12345));
+        assert_eq!(screen.render(), "#\n\n=-\n\n\n      o\nScore: 12345\n");
+    }
+
+    #[test]
+    fn frames_end_when_input_is_requested() {
+        let dir = std::env::temp_dir().join(format!("aoc2019-frames-{}", std::process::id()));
+        // Draws a wall at (0, 0), waits for input, then a ball at (1, 0)
+        let program = vec![104, 0, 104, 0, 104, 1, 3, 13, 104, 1, 104, 0, 104, 4, 99];
+        let mut cpu = IntcodeCPU::with_io(program, Screen::new(Palette::arcade()).frame_dir(&dir));
+
+        assert_eq!(cpu.run().unwrap(), CPUState::AwaitingInput);
+        assert_eq!(cpu.io().frames(), 1);
+        assert_eq!(cpu.io().error(), None);
+        assert_eq!(
+            fs::read_to_string(dir.join("frame-00001.txt")).unwrap(),
+            "#\n"
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,53 @@
+use super::io::IntcodeIO;
+use super::memory::Memory;
+use super::observer::Observer;
+use super::{CPUResult, CPUState, IntcodeCPU};
+
+/// An Intcode implementation that can be driven and inspected from outside,
+/// so that alternative engines can be checked against the shared CPU.
+pub trait Engine {
+    type IO: IntcodeIO;
+
+    /// Executes a single instruction
+    fn step(&mut self) -> CPUResult<CPUState>;
+
+    /// Executes until the program halts or waits for input
+    fn run(&mut self) -> CPUResult<CPUState> {
+        loop {
+            match self.step()? {
+                CPUState::Running => continue,
+                state => return Ok(state),
+            }
+        }
+    }
+
+    fn pc(&self) -> usize;
+
+    fn memory(&self) -> &Memory;
+
+    fn io(&self) -> &Self::IO;
+}
+
+impl<IO: IntcodeIO, O: Observer> Engine for IntcodeCPU<IO, O> {
+    type IO = IO;
+
+    fn step(&mut self) -> CPUResult<CPUState> {
+        IntcodeCPU::step(self)
+    }
+
+    fn run(&mut self) -> CPUResult<CPUState> {
+        IntcodeCPU::run(self)
+    }
+
+    fn pc(&self) -> usize {
+        self.pc
+    }
+
+    fn memory(&self) -> &Memory {
+        &self.program
+    }
+
+    fn io(&self) -> &IO {
+        &self.io
+    }
+}
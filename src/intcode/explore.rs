@@ -0,0 +1,255 @@
+use std::collections::{HashSet, VecDeque};
+use std::hash::Hash;
+
+use super::io::QueueIO;
+use super::{CPUException, CPUState, IntcodeCPU};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Order {
+    BreadthFirst,
+    DepthFirst,
+}
+
+/// A distinct state found by an `Explorer`
+#[derive(Clone)]
+pub struct Visit<S> {
+    pub state: S,
+    /// The choices that led here from the initial CPU
+    pub inputs: Vec<i32>,
+    /// The CPU after the last choice, ready to be forked again
+    pub cpu: IntcodeCPU<QueueIO>,
+}
+
+/// A choice for which the program raised an exception
+#[derive(Clone, Debug)]
+pub struct ExploreFailure {
+    pub inputs: Vec<i32>,
+    pub exception: CPUException,
+    pub pc: usize,
+}
+
+pub struct Exploration<S> {
+    /// Every distinct state, in the order it was first reached
+    pub visits: Vec<Visit<S>>,
+    pub failures: Vec<ExploreFailure>,
+}
+
+/// Explores a program that reads one choice at a time, by forking the CPU
+/// for every choice each time it waits for input.
+///
+/// What the program outputs after each choice is folded into a state of
+/// the caller's making, such as a position in a maze, and forks reaching a
+/// state already seen are dropped. Each fork has its own copy of any
+/// attached devices.
+#[derive(Clone, Debug)]
+pub struct Explorer {
+    choices: Vec<i32>,
+    order: Order,
+    max_visits: Option<usize>,
+}
+
+impl Explorer {
+    /// Try each of `choices` as the next input
+    pub fn new<I: IntoIterator<Item = i32>>(choices: I) -> Self {
+        Explorer {
+            choices: choices.into_iter().collect(),
+            order: Order::BreadthFirst,
+            max_visits: None,
+        }
+    }
+
+    pub fn order(mut self, order: Order) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Stop once this many distinct states have been found
+    pub fn max_visits(mut self, max: usize) -> Self {
+        self.max_visits = Some(max);
+        self
+    }
+
+    /// Finds every state reachable from `cpu`, which starts in `initial`.
+    ///
+    /// `observe(state, choice, outputs)` gives the state reached by feeding
+    /// `choice` in `state`, given what the program output in response, or
+    /// `None` to prune the branch.
+    pub fn explore<S, F>(&self, cpu: IntcodeCPU<QueueIO>, initial: S, observe: F) -> Exploration<S>
+    where
+        S: Clone + Eq + Hash,
+        F: FnMut(&S, i32, &[i32]) -> Option<S>,
+    {
+        self.walk(cpu, initial, observe, |_| false)
+    }
+
+    /// Explores until a state satisfying `goal` is found. Breadth first,
+    /// this is reached by the fewest choices.
+    pub fn find<S, F, G>(
+        &self,
+        cpu: IntcodeCPU<QueueIO>,
+        initial: S,
+        observe: F,
+        goal: G,
+    ) -> Option<Visit<S>>
+    where
+        S: Clone + Eq + Hash,
+        F: FnMut(&S, i32, &[i32]) -> Option<S>,
+        G: Fn(&S) -> bool,
+    {
+        self.walk(cpu, initial, observe, &goal)
+            .visits
+            .pop()
+            .filter(|visit| goal(&visit.state))
+    }
+
+    fn walk<S, F, G>(
+        &self,
+        mut cpu: IntcodeCPU<QueueIO>,
+        initial: S,
+        mut observe: F,
+        stop: G,
+    ) -> Exploration<S>
+    where
+        S: Clone + Eq + Hash,
+        F: FnMut(&S, i32, &[i32]) -> Option<S>,
+        G: Fn(&S) -> bool,
+    {
+        let mut exploration = Exploration {
+            visits: vec![],
+            failures: vec![],
+        };
+
+        if let Err(exception) = cpu.run() {
+            exploration.failures.push(ExploreFailure {
+                inputs: vec![],
+                exception,
+                pc: cpu.pc(),
+            });
+            return exploration;
+        }
+
+        let mut seen = HashSet::new();
+        seen.insert(initial.clone());
+        let done = stop(&initial) || self.max_visits == Some(1);
+        exploration.visits.push(Visit {
+            state: initial,
+            inputs: vec![],
+            cpu,
+        });
+        if done {
+            return exploration;
+        }
+
+        // Indices into the visits still to be expanded
+        let mut pending = VecDeque::from(vec![0]);
+
+        loop {
+            let index = match self.order {
+                Order::BreadthFirst => pending.pop_front(),
+                Order::DepthFirst => pending.pop_back(),
+            };
+            let visit = match index {
+                Some(index) => &exploration.visits[index],
+                None => break,
+            };
+            if visit.cpu.state() == CPUState::Halted {
+                continue;
+            }
+
+            let mut found = vec![];
+            for &choice in &self.choices {
+                let mut inputs = visit.inputs.clone();
+                inputs.push(choice);
+
+                let mut fork = visit.cpu.fork();
+                fork.io_mut().take_outputs();
+                fork.io_mut().push_input(choice);
+
+                if let Err(exception) = fork.run() {
+                    exploration.failures.push(ExploreFailure {
+                        inputs,
+                        exception,
+                        pc: fork.pc(),
+                    });
+                    continue;
+                }
+
+                if let Some(state) = observe(&visit.state, choice, fork.io().outputs()) {
+                    if seen.insert(state.clone()) {
+                        found.push(Visit {
+                            state,
+                            inputs,
+                            cpu: fork,
+                        });
+                    }
+                }
+            }
+
+            for visit in found {
+                let done = stop(&visit.state)
+                    || self
+                        .max_visits
+                        .is_some_and(|max| exploration.visits.len() + 1 >= max);
+                pending.push_back(exploration.visits.len());
+                exploration.visits.push(visit);
+                if done {
+                    return exploration;
+                }
+            }
+        }
+
+        exploration
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Keeps a counter that each input of 1 increments and any other input
+    /// decrements, outputting it after every input
+    fn counter() -> IntcodeCPU<QueueIO> {
+        let mut program = vec![
+            3, 30, 1008, 30, 1, 31, 1005, 31, 16, 1001, 32, -1, 32, 1105, 1, 20, 1001, 32, 1, 32,
+            4, 32, 1105, 1, 0,
+        ];
+        program.resize(33, 0);
+        IntcodeCPU::with_io(program, QueueIO::default())
+    }
+
+    fn observe(_: &i32, _: i32, outputs: &[i32]) -> Option<i32> {
+        outputs.last().copied().filter(|x| x.abs() <= 3)
+    }
+
+    #[test]
+    fn visits_each_observed_state_once() {
+        for &order in &[Order::BreadthFirst, Order::DepthFirst] {
+            let exploration = Explorer::new(vec![1, 2])
+                .order(order)
+                .explore(counter(), 0, observe);
+
+            let mut states = exploration
+                .visits
+                .iter()
+                .map(|visit| visit.state)
+                .collect::<Vec<_>>();
+            states.sort_unstable();
+            assert_eq!(states, vec![-3, -2, -1, 0, 1, 2, 3]);
+            assert!(exploration.failures.is_empty());
+        }
+    }
+
+    #[test]
+    fn breadth_first_finds_shortest_path() {
+        let visit = Explorer::new(vec![2, 1])
+            .find(counter(), 0, observe, |&x| x == 3)
+            .expect("Should have reached 3");
+        assert_eq!(visit.inputs, vec![1, 1, 1]);
+        assert_eq!(visit.cpu.get_position(32), Some(3));
+
+        let exploration = Explorer::new(vec![1, 2])
+            .max_visits(2)
+            .explore(counter(), 0, observe);
+        assert_eq!(exploration.visits.len(), 2);
+    }
+}
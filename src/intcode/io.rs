@@ -0,0 +1,244 @@
+use std::collections::VecDeque;
+use std::io::{BufRead, Write};
+use std::str::FromStr;
+
+use super::{CPUException, CPUExceptionKind, CPUResult};
+
+/// Where the `Input` and `Output` instructions read from and write to.
+pub trait IntcodeIO {
+    /// Returns the next input value, or `None` if nothing is available yet.
+    /// The CPU then stops in `CPUState::AwaitingInput` and retries the
+    /// instruction the next time it is stepped.
+    fn input(&mut self) -> CPUResult<Option<i32>>;
+
+    fn output(&mut self, value: i32);
+}
+
+/// Prompts for input on stdin and prints outputs to stdout.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct StdIO;
+
+impl IntcodeIO for StdIO {
+    fn input(&mut self) -> CPUResult<Option<i32>> {
+        let mut s = String::new();
+        print!("Input value: ");
+        std::io::stdout().flush().unwrap();
+        std::io::stdin().read_line(&mut s).map_err(|_| {
+            CPUException::new(
+                CPUExceptionKind::InvalidInput,
+                "Could not read input".into(),
+            )
+        })?;
+        let input = i32::from_str(s.trim()).map_err(|_| {
+            CPUException::new(
+                CPUExceptionKind::InvalidInput,
+                format!("Could not parse {} as i32", s.trim()),
+            )
+        })?;
+
+        Ok(Some(input))
+    }
+
+    fn output(&mut self, value: i32) {
+        println!("Program output: {}", value);
+    }
+}
+
+/// Feeds inputs from a queue and collects outputs in memory.
+#[derive(Clone, Debug, Default)]
+pub struct QueueIO {
+    inputs: VecDeque<i32>,
+    outputs: Vec<i32>,
+}
+
+impl QueueIO {
+    pub fn new<I: IntoIterator<Item = i32>>(inputs: I) -> Self {
+        QueueIO {
+            inputs: inputs.into_iter().collect(),
+            outputs: vec![],
+        }
+    }
+
+    pub fn push_input(&mut self, value: i32) {
+        self.inputs.push_back(value);
+    }
+
+    pub fn pending_inputs(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn outputs(&self) -> &[i32] {
+        &self.outputs
+    }
+
+    pub fn take_outputs(&mut self) -> Vec<i32> {
+        std::mem::take(&mut self.outputs)
+    }
+}
+
+impl IntcodeIO for QueueIO {
+    fn input(&mut self) -> CPUResult<Option<i32>> {
+        Ok(self.inputs.pop_front())
+    }
+
+    fn output(&mut self, value: i32) {
+        self.outputs.push(value);
+    }
+}
+
+/// Talks to programs that use ASCII: input is given as lines of text, and
+/// outputs are assembled into lines, with any value outside the ASCII range
+/// (usually an answer) kept aside as a number.
+///
+/// In interactive mode, completed lines and numbers are printed as they are
+/// output, and a line is read from stdin whenever the program needs input
+/// and none is queued.
+#[derive(Clone, Debug, Default)]
+pub struct AsciiIO {
+    inputs: VecDeque<i32>,
+    partial: String,
+    lines: Vec<String>,
+    values: Vec<i32>,
+    interactive: bool,
+}
+
+impl AsciiIO {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn interactive() -> Self {
+        AsciiIO {
+            interactive: true,
+            ..Self::default()
+        }
+    }
+
+    /// Queue `line` as input, followed by a newline
+    pub fn push_line(&mut self, line: &str) {
+        self.inputs.extend(line.chars().map(|c| c as i32));
+        self.inputs.push_back('\n' as i32);
+    }
+
+    /// Complete lines output so far
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+
+    pub fn take_lines(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.lines)
+    }
+
+    /// Text output since the last newline, such as a prompt
+    pub fn partial_line(&self) -> &str {
+        &self.partial
+    }
+
+    /// Outputs that weren't ASCII characters
+    pub fn values(&self) -> &[i32] {
+        &self.values
+    }
+
+    fn read_stdin_line(&mut self) -> CPUResult<bool> {
+        print!("{}", self.partial);
+        std::io::stdout().flush().unwrap();
+        self.partial.clear();
+
+        let mut s = String::new();
+        let read = std::io::stdin().lock().read_line(&mut s).map_err(|_| {
+            CPUException::new(
+                CPUExceptionKind::InvalidInput,
+                "Could not read input".into(),
+            )
+        })?;
+        if read == 0 {
+            return Ok(false);
+        }
+
+        if !s.is_ascii() {
+            return Err(CPUException::new(
+                CPUExceptionKind::InvalidInput,
+                format!("Input {} is not ASCII", s.trim_end()),
+            ));
+        }
+        self.push_line(s.trim_end_matches(&['\r', '\n'][..]));
+        Ok(true)
+    }
+}
+
+impl IntcodeIO for AsciiIO {
+    fn input(&mut self) -> CPUResult<Option<i32>> {
+        if self.inputs.is_empty() && self.interactive && !self.read_stdin_line()? {
+            return Ok(None);
+        }
+
+        Ok(self.inputs.pop_front())
+    }
+
+    fn output(&mut self, value: i32) {
+        match value {
+            10 => {
+                let line = std::mem::take(&mut self.partial);
+                if self.interactive {
+                    println!("{}", line);
+                }
+                self.lines.push(line);
+            }
+            0..=127 => self.partial.push(value as u8 as char),
+            _ => {
+                if self.interactive {
+                    println!("Program output: {}", value);
+                }
+                self.values.push(value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{CPUState, IntcodeCPU};
+
+    #[test]
+    fn waits_for_input_and_resumes() {
+        let program = vec![3, 9, 1002, 9, 2, 10, 4, 10, 99, 0, 0];
+        let mut cpu = IntcodeCPU::with_io(program, QueueIO::default());
+
+        let state = cpu.run().expect("Should not have excepted at runtime");
+        assert_eq!(state, CPUState::AwaitingInput);
+        assert_eq!(cpu.pc(), 0);
+
+        cpu.io_mut().push_input(21);
+        let state = cpu.run().expect("Should not have excepted at runtime");
+        assert_eq!(state, CPUState::Halted);
+        assert_eq!(cpu.io().outputs(), &[42]);
+    }
+
+    #[test]
+    fn ascii_lines_and_values_are_separated() {
+        let program = vec![104, 72, 104, 105, 104, 10, 104, 1000, 104, 62, 99];
+        let mut cpu = IntcodeCPU::with_io(program, AsciiIO::new());
+        cpu.run().expect("Should not have excepted at runtime");
+
+        assert_eq!(cpu.io().lines(), &["Hi".to_string()]);
+        assert_eq!(cpu.io().values(), &[1000]);
+        assert_eq!(cpu.io().partial_line(), ">");
+    }
+
+    #[test]
+    fn ascii_input_is_fed_by_line() {
+        // Output each input character plus one, until the newline
+        let program = vec![
+            3, 20, 1008, 20, 10, 21, 1005, 21, 19, 101, 1, 20, 20, 4, 20, 1105, 1, 0, 99, 99, 0, 0,
+        ];
+        let mut io = AsciiIO::new();
+        io.push_line("HAL");
+
+        let mut cpu = IntcodeCPU::with_io(program, io);
+        let state = cpu.run().expect("Should not have excepted at runtime");
+
+        assert_eq!(state, CPUState::Halted);
+        assert_eq!(cpu.io().partial_line(), "IBM");
+    }
+}
@@ -0,0 +1,325 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::process::Command;
+
+use super::display::Screen;
+use super::io::IntcodeIO;
+use super::loader::{LoadError, LoadResult};
+use super::{CPUException, CPUExceptionKind, CPUResult};
+
+pub const LEFT: i32 = -1;
+pub const NEUTRAL: i32 = 0;
+pub const RIGHT: i32 = 1;
+
+/// Ball and paddle tile ids, as used by the arcade cabinet
+const PADDLE: i32 = 3;
+const BALL: i32 = 4;
+
+/// Decides how the joystick is tilted each time the program reads it
+pub trait Joystick {
+    /// The next position, `LEFT`, `NEUTRAL` or `RIGHT`, given the screen as
+    /// the program has drawn it so far. `None` leaves the program waiting.
+    fn tilt(&mut self, screen: &Screen) -> CPUResult<Option<i32>>;
+}
+
+/// Follows a fixed sequence of moves, then leaves the program waiting
+#[derive(Clone, Debug, Default)]
+pub struct Scripted {
+    moves: VecDeque<i32>,
+}
+
+impl Scripted {
+    pub fn new<I: IntoIterator<Item = i32>>(moves: I) -> Self {
+        Scripted {
+            moves: moves.into_iter().collect(),
+        }
+    }
+
+    /// Parses moves separated by commas and/or whitespace, each one of
+    /// `L`, `N` or `R` (in either case) or `-1`, `0` or `1`. Anything from
+    /// `#` to the end of a line is a comment.
+    pub fn parse(text: &str) -> LoadResult<Self> {
+        let mut moves = VecDeque::new();
+
+        for (line_idx, line) in text.lines().enumerate() {
+            let code = line.split('#').next().unwrap_or("");
+            let mut column = 1;
+
+            for token in code.split(|c: char| c == ',' || c.is_whitespace()) {
+                let tilt = match token {
+                    "" => None,
+                    "L" | "l" | "-1" => Some(LEFT),
+                    "N" | "n" | "0" => Some(NEUTRAL),
+                    "R" | "r" | "1" => Some(RIGHT),
+                    _ => {
+                        return Err(LoadError::InvalidMove {
+                            line: line_idx + 1,
+                            column,
+                            token: token.to_string(),
+                        })
+                    }
+                };
+                moves.extend(tilt);
+                column += token.chars().count() + 1;
+            }
+        }
+
+        Ok(Scripted { moves })
+    }
+
+    pub fn load_path<P: AsRef<Path>>(path: P) -> LoadResult<Self> {
+        Scripted::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Number of moves not yet made
+    pub fn remaining(&self) -> usize {
+        self.moves.len()
+    }
+}
+
+impl Joystick for Scripted {
+    fn tilt(&mut self, _screen: &Screen) -> CPUResult<Option<i32>> {
+        Ok(self.moves.pop_front())
+    }
+}
+
+/// Asks a closure that sees the screen, for playing without a human
+pub struct Policy<F> {
+    decide: F,
+}
+
+impl<F: FnMut(&Screen) -> i32> Policy<F> {
+    pub fn new(decide: F) -> Self {
+        Policy { decide }
+    }
+}
+
+impl<F: FnMut(&Screen) -> i32> Joystick for Policy<F> {
+    fn tilt(&mut self, screen: &Screen) -> CPUResult<Option<i32>> {
+        Ok(Some((self.decide)(screen)))
+    }
+}
+
+/// Moves the paddle towards the ball, which never misses it
+pub fn track_ball(screen: &Screen) -> i32 {
+    match (screen.find(BALL), screen.find(PADDLE)) {
+        (Some((ball, _)), Some((paddle, _))) => (ball - paddle).signum(),
+        _ => NEUTRAL,
+    }
+}
+
+/// Reads the arrow keys (or `a`/`d`, `h`/`l`) from the terminal, one key
+/// per move. Any other key leaves the joystick neutral, and `q` quits.
+///
+/// The terminal is switched out of line mode with `stty` for as long as
+/// the keyboard exists. A lone Esc is told apart from an arrow key by
+/// waiting a tenth of a second for the rest of the sequence, and counts as
+/// any other key.
+pub struct Keyboard {
+    saved_mode: String,
+}
+
+fn stty(args: &[&str]) -> io::Result<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(File::open("/dev/tty")?)
+        .output()?;
+
+    if !output.status.success() {
+        return Err(io::Error::other(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// The tilt a key press asks for, or `None` to quit
+fn tilt_for_key(key: &[u8]) -> Option<i32> {
+    match key {
+        b"\x1b[D" | b"a" | b"h" => Some(LEFT),
+        b"\x1b[C" | b"d" | b"l" => Some(RIGHT),
+        b"q" | b"\x03" | b"\x04" => None,
+        _ => Some(NEUTRAL),
+    }
+}
+
+/// Fills as much of `buf` as arrives before a read returns nothing,
+/// returning how much that was
+fn read_up_to<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match reader.read(&mut buf[len..])? {
+            0 => break,
+            n => len += n,
+        }
+    }
+    Ok(len)
+}
+
+impl Keyboard {
+    pub fn new() -> io::Result<Self> {
+        let saved_mode = stty(&["-g"])?;
+        stty(&["-icanon", "-echo", "min", "1", "time", "0"])?;
+        Ok(Keyboard { saved_mode })
+    }
+
+    fn read_key(&mut self) -> io::Result<Vec<u8>> {
+        let mut stdin = io::stdin();
+        let mut key = vec![0];
+        if stdin.read(&mut key)? == 0 {
+            return Ok(b"q".to_vec());
+        }
+
+        // Arrow keys arrive as escape sequences. Reads time out while the
+        // rest is read, so that a lone Esc doesn't wait for the next key.
+        if key[0] == 0x1b {
+            stty(&["min", "0", "time", "1"])?;
+            let mut rest = [0; 2];
+            let read = read_up_to(&mut stdin, &mut rest);
+            stty(&["min", "1", "time", "0"])?;
+            key.extend_from_slice(&rest[..read?]);
+        }
+
+        Ok(key)
+    }
+}
+
+impl Joystick for Keyboard {
+    fn tilt(&mut self, _screen: &Screen) -> CPUResult<Option<i32>> {
+        let key = self.read_key().map_err(|e| {
+            CPUException::new(
+                CPUExceptionKind::InvalidInput,
+                format!("Could not read key: {}", e),
+            )
+        })?;
+
+        match tilt_for_key(&key) {
+            Some(tilt) => Ok(Some(tilt)),
+            None => Err(CPUException::new(
+                CPUExceptionKind::InvalidInput,
+                "Player quit".into(),
+            )),
+        }
+    }
+}
+
+impl Drop for Keyboard {
+    fn drop(&mut self) {
+        let _ = stty(&[&self.saved_mode]);
+    }
+}
+
+/// A screen for the program's outputs and a joystick for its inputs. Each
+/// time the joystick is read the frame drawn so far is ended first, so the
+/// joystick always sees the latest picture.
+pub struct Arcade<J> {
+    screen: Screen,
+    joystick: J,
+}
+
+impl<J: Joystick> Arcade<J> {
+    pub fn new(screen: Screen, joystick: J) -> Self {
+        Arcade { screen, joystick }
+    }
+
+    pub fn screen(&self) -> &Screen {
+        &self.screen
+    }
+
+    pub fn screen_mut(&mut self) -> &mut Screen {
+        &mut self.screen
+    }
+
+    pub fn joystick(&self) -> &J {
+        &self.joystick
+    }
+}
+
+impl<J: Joystick> IntcodeIO for Arcade<J> {
+    fn input(&mut self) -> CPUResult<Option<i32>> {
+        self.screen.end_frame_quietly();
+        self.joystick.tilt(&self.screen)
+    }
+
+    fn output(&mut self, value: i32) {
+        self.screen.push(value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::display::Palette;
+    use crate::intcode::{CPUState, IntcodeCPU};
+
+    /// Draws a ball at (0, 0) and a paddle at ([27], 1), then forever
+    /// reads the joystick and moves the paddle by that much
+    fn paddle_game() -> Vec<i32> {
+        vec![
+            104, 0, 104, 0, 104, 4, // ball
+            4, 27, 104, 1, 104, 3, // paddle
+            3, 28, 4, 27, 104, 1, 104, 0, // read, then erase the paddle
+            1, 27, 28, 27, 1105, 1, 6, // move it and draw again
+            0, 0,
+        ]
+    }
+
+    #[test]
+    fn scripted_moves_are_parsed() {
+        let script = Scripted::parse("L, l,-1 # left\nN 0\n\nR,r ,1,\n").unwrap();
+        assert_eq!(
+            script.moves,
+            vec![LEFT, LEFT, LEFT, NEUTRAL, NEUTRAL, RIGHT, RIGHT, RIGHT]
+        );
+
+        match Scripted::parse("L,R\nN, up") {
+            Err(e @ LoadError::InvalidMove { .. }) => assert_eq!(
+                e.to_string(),
+                "2:4: 'up' is not a move, expected one of L, N, R, -1, 0 or 1"
+            ),
+            other => panic!("unexpected result {:?}", other.map(|s| s.moves)),
+        }
+    }
+
+    #[test]
+    fn scripted_and_policy_joysticks_move_the_paddle() {
+        let screen = Screen::new(Palette::arcade()).score_at(None);
+        let arcade = Arcade::new(screen.clone(), Scripted::new(vec![RIGHT, RIGHT, LEFT]));
+        let mut cpu = IntcodeCPU::with_io(paddle_game(), arcade);
+
+        assert_eq!(cpu.run().unwrap(), CPUState::AwaitingInput);
+        assert_eq!(cpu.io().screen().find(3), Some((1, 1)));
+        assert_eq!(cpu.io().screen().count(3), 1);
+        assert_eq!(cpu.io().screen().frames(), 4);
+        assert_eq!(cpu.io().joystick().remaining(), 0);
+
+        let mut program = paddle_game();
+        program[27] = 5;
+        let arcade = Arcade::new(screen, Policy::new(track_ball));
+        let mut cpu = IntcodeCPU::with_io(program, arcade);
+        cpu.set_step_limit(Some(100));
+        cpu.run().expect_err("Should have run out of steps");
+        assert_eq!(cpu.get_position(27), Some(0));
+    }
+
+    #[test]
+    fn keys_map_to_tilts() {
+        assert_eq!(tilt_for_key(b"\x1b[D"), Some(LEFT));
+        assert_eq!(tilt_for_key(b"l"), Some(RIGHT));
+        assert_eq!(tilt_for_key(b" "), Some(NEUTRAL));
+        assert_eq!(tilt_for_key(b"q"), None);
+        // Esc on its own, or cut short, is just another key
+        assert_eq!(tilt_for_key(b"\x1b"), Some(NEUTRAL));
+        assert_eq!(tilt_for_key(b"\x1b["), Some(NEUTRAL));
+    }
+
+    #[test]
+    fn escape_sequences_may_be_cut_short() {
+        let mut rest = [0; 2];
+        assert_eq!(read_up_to(&mut &b"[D"[..], &mut rest).unwrap(), 2);
+        assert_eq!(&rest, b"[D");
+        assert_eq!(read_up_to(&mut &b""[..], &mut rest).unwrap(), 0);
+    }
+}
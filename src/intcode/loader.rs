@@ -0,0 +1,270 @@
+use std::fmt;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+use std::str::FromStr;
+
+/// First bytes of a binary image: a little-endian `u32` count of cells
+/// follows, then each cell as a little-endian `i32`.
+pub const IMAGE_MAGIC: &[u8; 4] = b"ICI1";
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    /// A token that isn't an `i32`, at a 1-based line and column
+    InvalidValue {
+        line: usize,
+        column: usize,
+        token: String,
+    },
+    /// A joystick script token that isn't a move, at a 1-based line and
+    /// column
+    InvalidMove {
+        line: usize,
+        column: usize,
+        token: String,
+    },
+    /// Two separating commas with nothing between them
+    MissingValue {
+        line: usize,
+        column: usize,
+    },
+    /// A binary image too short to hold its cell count
+    TruncatedHeader {
+        found: usize,
+    },
+    /// A binary image whose length doesn't match its header
+    TruncatedImage {
+        expected: usize,
+        found: usize,
+    },
+    /// Not a binary image, and not valid UTF-8 text either
+    NotText,
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io(e) => write!(f, "Could not read program: {}", e),
+            LoadError::InvalidValue {
+                line,
+                column,
+                token,
+            } => write!(
+                f,
+                "{}:{}: could not interpret '{}' as i32",
+                line, column, token
+            ),
+            LoadError::InvalidMove {
+                line,
+                column,
+                token,
+            } => write!(
+                f,
+                "{}:{}: '{}' is not a move, expected one of L, N, R, -1, 0 or 1",
+                line, column, token
+            ),
+            LoadError::MissingValue { line, column } => {
+                write!(f, "{}:{}: expected a value before ','", line, column)
+            }
+            LoadError::TruncatedHeader { found } => write!(
+                f,
+                "Binary image header should be 4 bytes after the magic but has {}",
+                found
+            ),
+            LoadError::TruncatedImage { expected, found } => write!(
+                f,
+                "Binary image should hold {} cells but has bytes for {}",
+                expected, found
+            ),
+            LoadError::NotText => write!(f, "Program is neither a binary image nor text"),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+impl From<io::Error> for LoadError {
+    fn from(e: io::Error) -> Self {
+        LoadError::Io(e)
+    }
+}
+
+pub type LoadResult<T> = Result<T, LoadError>;
+
+/// Parses a program written as integers separated by commas and/or
+/// whitespace. Anything from `#` to the end of a line is a comment, and a
+/// trailing comma is allowed.
+pub fn parse(text: &str) -> LoadResult<Vec<i32>> {
+    let mut program = vec![];
+
+    // Whether a comma has been seen since the last value
+    let mut pending_comma = false;
+    let mut seen_value = false;
+
+    for (line_idx, line) in text.lines().enumerate() {
+        let code = line.split('#').next().unwrap_or("");
+        let mut chars = code.char_indices().peekable();
+        // 1-based column of the next char, counted as we go since inputs
+        // are usually one very long line
+        let mut next_column = 1;
+
+        while let Some(&(start, c)) = chars.peek() {
+            let (line, column) = (line_idx + 1, next_column);
+
+            if c.is_whitespace() {
+                chars.next();
+                next_column += 1;
+            } else if c == ',' {
+                if pending_comma || !seen_value {
+                    return Err(LoadError::MissingValue { line, column });
+                }
+                pending_comma = true;
+                chars.next();
+                next_column += 1;
+            } else {
+                let mut end = start;
+                while let Some(&(idx, c)) = chars.peek() {
+                    if c.is_whitespace() || c == ',' {
+                        break;
+                    }
+                    end = idx + c.len_utf8();
+                    chars.next();
+                    next_column += 1;
+                }
+
+                let token = &code[start..end];
+                let value = i32::from_str(token).map_err(|_| LoadError::InvalidValue {
+                    line,
+                    column,
+                    token: token.to_string(),
+                })?;
+                program.push(value);
+                pending_comma = false;
+                seen_value = true;
+            }
+        }
+    }
+
+    Ok(program)
+}
+
+/// Encodes a program in the binary image format
+pub fn to_image(program: &[i32]) -> Vec<u8> {
+    let mut image = Vec::with_capacity(8 + program.len() * 4);
+    image.extend_from_slice(IMAGE_MAGIC);
+    image.extend_from_slice(&(program.len() as u32).to_le_bytes());
+    for val in program {
+        image.extend_from_slice(&val.to_le_bytes());
+    }
+    image
+}
+
+/// Decodes a binary image, which must start with `IMAGE_MAGIC`
+fn from_image(bytes: &[u8]) -> LoadResult<Vec<i32>> {
+    let body = &bytes[IMAGE_MAGIC.len()..];
+    if body.len() < 4 {
+        return Err(LoadError::TruncatedHeader { found: body.len() });
+    }
+
+    let (count, cells) = body.split_at(4);
+    let expected = u32::from_le_bytes([count[0], count[1], count[2], count[3]]) as usize;
+    if cells.len() != expected * 4 {
+        return Err(LoadError::TruncatedImage {
+            expected,
+            found: cells.len() / 4,
+        });
+    }
+
+    Ok(cells
+        .chunks_exact(4)
+        .map(|c| i32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+        .collect())
+}
+
+/// Loads a program from either format, telling them apart by the magic
+pub fn from_bytes(bytes: &[u8]) -> LoadResult<Vec<i32>> {
+    if bytes.starts_with(IMAGE_MAGIC) {
+        return from_image(bytes);
+    }
+
+    let text = std::str::from_utf8(bytes).map_err(|_| LoadError::NotText)?;
+    parse(text)
+}
+
+pub fn load_path<P: AsRef<Path>>(path: P) -> LoadResult<Vec<i32>> {
+    from_bytes(&fs::read(path)?)
+}
+
+pub fn load_reader<R: Read>(mut reader: R) -> LoadResult<Vec<i32>> {
+    let mut bytes = vec![];
+    reader.read_to_end(&mut bytes)?;
+    from_bytes(&bytes)
+}
+
+pub fn load_stdin() -> LoadResult<Vec<i32>> {
+    load_reader(io::stdin().lock())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_comments_whitespace_and_trailing_comma() {
+        let text = "# doubles its input\n3,9, 1002,9,2,10 # mul\n\t4,10,99,\n0,0,\n";
+
+        assert_eq!(
+            parse(text).unwrap(),
+            vec![3, 9, 1002, 9, 2, 10, 4, 10, 99, 0, 0]
+        );
+    }
+
+    #[test]
+    fn reports_position_of_bad_tokens() {
+        match parse("1,2,3\n4, 5x,6") {
+            Err(LoadError::InvalidValue {
+                line,
+                column,
+                token,
+            }) => assert_eq!((line, column, token.as_str()), (2, 4, "5x")),
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        match parse("1,,2") {
+            Err(LoadError::MissingValue { line, column }) => assert_eq!((line, column), (1, 3)),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn binary_image_round_trips() {
+        let program = vec![1, -2, 3_000_000, 99];
+        let image = to_image(&program);
+
+        assert_eq!(from_bytes(&image).unwrap(), program);
+        assert!(matches!(
+            from_bytes(&image[..image.len() - 1]),
+            Err(LoadError::TruncatedImage {
+                expected: 4,
+                found: 3
+            })
+        ));
+        assert!(matches!(
+            from_bytes(&image[..6]),
+            Err(LoadError::TruncatedHeader { found: 2 })
+        ));
+    }
+
+    #[test]
+    fn long_lines_parse_quickly() {
+        let text = vec!["1"; 200_000].join(",");
+        let program = parse(&text).unwrap();
+        assert_eq!(program.len(), 200_000);
+
+        match parse(&format!("{},x", text)) {
+            Err(LoadError::InvalidValue { column, .. }) => assert_eq!(column, 400_001),
+            other => panic!("unexpected result {:?}", other.map(|p| p.len())),
+        }
+    }
+}
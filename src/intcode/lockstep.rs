@@ -0,0 +1,314 @@
+use std::fmt;
+
+use super::engine::Engine;
+use super::io::QueueIO;
+use super::{CPUResult, CPUState};
+
+/// What an engine looked like after a step
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    pub pc: usize,
+    pub result: CPUResult<CPUState>,
+    pub outputs: Vec<i32>,
+    pub memory: Vec<i32>,
+}
+
+impl Snapshot {
+    fn take<E: Engine<IO = QueueIO>>(engine: &E, result: &CPUResult<CPUState>) -> Self {
+        Snapshot {
+            pc: engine.pc(),
+            result: result.clone(),
+            outputs: engine.io().outputs().to_vec(),
+            memory: engine.memory().to_vec(),
+        }
+    }
+
+    fn result_text(&self) -> String {
+        match &self.result {
+            Ok(state) => format!("{:?}", state),
+            Err(ex) => ex.to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DivergenceKind {
+    /// One engine raised an exception or changed state when the other didn't
+    Result,
+    Pc,
+    Outputs,
+    /// Memory differs, first at this address
+    Memory(usize),
+}
+
+/// The first point at which two engines disagreed
+#[derive(Clone, Debug)]
+pub struct Divergence {
+    /// Number of steps both engines executed, including the diverging one
+    pub step: u64,
+    pub kind: DivergenceKind,
+    pub a: Snapshot,
+    pub b: Snapshot,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let what = match self.kind {
+            DivergenceKind::Result => "step result".to_string(),
+            DivergenceKind::Pc => "pc".to_string(),
+            DivergenceKind::Outputs => "outputs".to_string(),
+            DivergenceKind::Memory(pos) => format!("memory at [{}]", pos),
+        };
+        writeln!(f, "Engines diverged in {} at step {}", what, self.step)?;
+
+        for (name, snap) in &[("A", &self.a), ("B", &self.b)] {
+            writeln!(
+                f,
+                "  {}: pc {}, {}, outputs {:?}",
+                name,
+                snap.pc,
+                snap.result_text(),
+                snap.outputs
+            )?;
+        }
+
+        let len = self.a.memory.len().max(self.b.memory.len());
+        for pos in 0..len {
+            let (a, b) = (self.a.memory.get(pos), self.b.memory.get(pos));
+            if a != b {
+                let show = |val: Option<&i32>| val.map_or("-".to_string(), i32::to_string);
+                writeln!(f, "  [{}]: A {}, B {}", pos, show(a), show(b))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Compares the engines as they stand, so that nothing is copied unless they
+/// have diverged
+fn compare<A, B>(
+    a: &A,
+    res_a: &CPUResult<CPUState>,
+    b: &B,
+    res_b: &CPUResult<CPUState>,
+) -> Option<DivergenceKind>
+where
+    A: Engine<IO = QueueIO>,
+    B: Engine<IO = QueueIO>,
+{
+    // Exceptions are compared by kind only, as messages may differ between
+    // engines
+    let same_result = match (res_a, res_b) {
+        (Ok(x), Ok(y)) => x == y,
+        (Err(x), Err(y)) => x.kind() == y.kind(),
+        _ => false,
+    };
+    if !same_result {
+        return Some(DivergenceKind::Result);
+    }
+
+    if a.pc() != b.pc() {
+        return Some(DivergenceKind::Pc);
+    }
+
+    if a.io().outputs() != b.io().outputs() {
+        return Some(DivergenceKind::Outputs);
+    }
+
+    let (mem_a, mem_b) = (a.memory(), b.memory());
+    if mem_a != mem_b {
+        let first = (0..mem_a.len().max(mem_b.len()))
+            .find(|&pos| mem_a.get(pos) != mem_b.get(pos))
+            .unwrap_or(0);
+        return Some(DivergenceKind::Memory(first));
+    }
+
+    None
+}
+
+/// Steps both engines together until both halt, raise the same exception or
+/// wait for input, or `max_steps` have run, returning the number of steps.
+/// Stops at the first step after which pc, memory or outputs differ.
+pub fn run_lockstep<A, B>(a: &mut A, b: &mut B, max_steps: u64) -> Result<u64, Box<Divergence>>
+where
+    A: Engine<IO = QueueIO>,
+    B: Engine<IO = QueueIO>,
+{
+    for step in 1..=max_steps {
+        let res_a = a.step();
+        let res_b = b.step();
+
+        if let Some(kind) = compare(a, &res_a, b, &res_b) {
+            return Err(Box::new(Divergence {
+                step,
+                kind,
+                a: Snapshot::take(a, &res_a),
+                b: Snapshot::take(b, &res_b),
+            }));
+        }
+
+        match res_a {
+            Ok(CPUState::Running) => {}
+            _ => return Ok(step),
+        }
+    }
+
+    Ok(max_steps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::conformance::check_all;
+    use crate::intcode::io::IntcodeIO;
+    use crate::intcode::memory::Memory;
+    use crate::intcode::{CPUException, IntcodeCPU};
+
+    /// A deliberately naive second implementation, optionally with the old
+    /// bug of taking `Input`'s destination from `pc + 3`
+    struct Reference {
+        memory: Memory,
+        pc: usize,
+        io: QueueIO,
+        input_from_pc3: bool,
+    }
+
+    impl Reference {
+        fn new(memory: Vec<i32>, io: QueueIO) -> Self {
+            Reference {
+                memory: Memory::from(memory),
+                pc: 0,
+                io,
+                input_from_pc3: false,
+            }
+        }
+
+        fn cell(&self, pos: usize) -> CPUResult<i32> {
+            self.memory
+                .get(pos)
+                .ok_or_else(|| CPUException::out_of_bounds("REF", pos))
+        }
+
+        fn param(&self, n: usize) -> CPUResult<i32> {
+            let raw = self.cell(self.pc + n)?;
+            let mode = (self.cell(self.pc)? / 10i32.pow(n as u32 + 1)) % 10;
+            match mode {
+                0 => self.cell(raw as usize),
+                1 => Ok(raw),
+                _ => Err(CPUException::invalid_operand((b'0' + mode as u8) as char)),
+            }
+        }
+
+        fn dst(&self, n: usize) -> CPUResult<usize> {
+            let mode = (self.cell(self.pc)? / 10i32.pow(n as u32 + 1)) % 10;
+            match mode {
+                0 => Ok(self.cell(self.pc + n)? as usize),
+                1 => Err(CPUException::immediate_destination("REF", self.pc)),
+                _ => Err(CPUException::invalid_operand((b'0' + mode as u8) as char)),
+            }
+        }
+
+        fn store(&mut self, pos: usize, val: i32) -> CPUResult<()> {
+            self.memory
+                .set(pos, val)
+                .map(|_| ())
+                .ok_or_else(|| CPUException::out_of_bounds("REF", pos))
+        }
+    }
+
+    impl Engine for Reference {
+        type IO = QueueIO;
+
+        fn step(&mut self) -> CPUResult<CPUState> {
+            let opcode = self.cell(self.pc)?;
+            match opcode % 100 {
+                1 | 2 | 7 | 8 => {
+                    let (x, y, dst) = (self.param(1)?, self.param(2)?, self.dst(3)?);
+                    let val = match opcode % 100 {
+                        1 => x.checked_add(y),
+                        2 => x.checked_mul(y),
+                        7 => Some(i32::from(x < y)),
+                        _ => Some(i32::from(x == y)),
+                    }
+                    .ok_or_else(|| CPUException::overflow("REF", self.pc))?;
+                    self.store(dst, val)?;
+                    self.pc += 4;
+                }
+                3 => {
+                    let dst = self.dst(if self.input_from_pc3 { 3 } else { 1 })?;
+                    if dst >= self.memory.len() {
+                        return Err(CPUException::out_of_bounds("REF", dst));
+                    }
+                    match self.io.input()? {
+                        Some(val) => self.store(dst, val)?,
+                        None => return Ok(CPUState::AwaitingInput),
+                    }
+                    self.pc += 2;
+                }
+                4 => {
+                    let val = self.param(1)?;
+                    self.io.output(val);
+                    self.pc += 2;
+                }
+                5 | 6 => {
+                    let (cmp, to) = (self.param(1)?, self.param(2)?);
+                    if (cmp != 0) == (opcode % 100 == 5) {
+                        self.pc = to as usize;
+                    } else {
+                        self.pc += 3;
+                    }
+                }
+                99 => return Ok(CPUState::Halted),
+                _ => return Err(CPUException::invalid_opcode(opcode)),
+            }
+
+            Ok(CPUState::Running)
+        }
+
+        fn pc(&self) -> usize {
+            self.pc
+        }
+
+        fn memory(&self) -> &Memory {
+            &self.memory
+        }
+
+        fn io(&self) -> &QueueIO {
+            &self.io
+        }
+    }
+
+    #[test]
+    fn reference_engine_conforms() {
+        let mismatches = check_all(Reference::new);
+        let names = mismatches.iter().map(|m| m.case).collect::<Vec<_>>();
+
+        assert!(names.is_empty(), "{:?}", names);
+    }
+
+    #[test]
+    fn agreeing_engines_run_to_completion() {
+        let program = vec![3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8];
+        let mut a = IntcodeCPU::with_io(program.clone(), QueueIO::new(vec![8]));
+        let mut b = Reference::new(program, QueueIO::new(vec![8]));
+
+        assert_eq!(run_lockstep(&mut a, &mut b, 100).unwrap(), 4);
+    }
+
+    #[test]
+    fn stops_at_first_divergence() {
+        let program = vec![3, 6, 104, 1, 99, 0, 0];
+        let mut a = IntcodeCPU::with_io(program.clone(), QueueIO::new(vec![-7]));
+        let mut b = Reference::new(program, QueueIO::new(vec![-7]));
+        b.input_from_pc3 = true;
+
+        let divergence = run_lockstep(&mut a, &mut b, 100).unwrap_err();
+
+        assert_eq!(divergence.step, 1);
+        assert_eq!(divergence.kind, DivergenceKind::Memory(1));
+        let report = divergence.to_string();
+        assert!(report.contains("[1]: A 6, B -7"));
+        assert!(report.contains("[6]: A -7, B 0"));
+    }
+}
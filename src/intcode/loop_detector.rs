@@ -0,0 +1,123 @@
+use super::memory::Memory;
+
+/// Detects exact repeats of the (pc, memory) state.
+///
+/// A hash of memory is kept up to date on every write, and the current state
+/// is compared against a checkpoint that moves forward at power-of-two step
+/// counts (Brent's algorithm), so any cycle is found with a single saved
+/// snapshot, kept as a copy-on-write clone of memory. A hash match is
+/// confirmed against the snapshot before a loop is reported. Consuming
+/// input resets the checkpoint, since a repeated state is only a definite
+/// loop if nothing external was read in between; the CPU does the same
+/// after any access to a memory-mapped device.
+#[derive(Clone, Debug)]
+pub(super) struct LoopDetector {
+    memory_hash: u64,
+    saved_pc: usize,
+    saved_hash: u64,
+    saved_memory: Memory,
+    saved_step: u64,
+    interval: u64,
+}
+
+fn mix(pos: usize, val: i32) -> u64 {
+    // splitmix64 finaliser over the (address, value) pair
+    let mut z = ((pos as u64) << 32) ^ u64::from(val as u32);
+    z = z.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+impl LoopDetector {
+    pub(super) fn new(pc: usize, memory: &Memory, step: u64) -> Self {
+        let memory_hash = memory
+            .iter()
+            .enumerate()
+            .fold(0u64, |hash, (pos, val)| hash.wrapping_add(mix(pos, val)));
+
+        LoopDetector {
+            memory_hash,
+            saved_pc: pc,
+            saved_hash: memory_hash,
+            saved_memory: memory.clone(),
+            saved_step: step,
+            interval: 1,
+        }
+    }
+
+    pub(super) fn record_write(&mut self, pos: usize, old: i32, new: i32) {
+        self.memory_hash = self
+            .memory_hash
+            .wrapping_sub(mix(pos, old))
+            .wrapping_add(mix(pos, new));
+    }
+
+    pub(super) fn record_input(&mut self, pc: usize, memory: &Memory, step: u64) {
+        self.checkpoint(pc, memory, step);
+        self.interval = 1;
+    }
+
+    fn checkpoint(&mut self, pc: usize, memory: &Memory, step: u64) {
+        self.saved_pc = pc;
+        self.saved_hash = self.memory_hash;
+        self.saved_memory = memory.clone();
+        self.saved_step = step;
+    }
+
+    /// Returns the length of the cycle if this state has been seen before
+    pub(super) fn check(&mut self, pc: usize, memory: &Memory, step: u64) -> Option<u64> {
+        if step > self.saved_step
+            && pc == self.saved_pc
+            && self.memory_hash == self.saved_hash
+            && *memory == self.saved_memory
+        {
+            return Some(step - self.saved_step);
+        }
+
+        if step - self.saved_step >= self.interval {
+            self.checkpoint(pc, memory, step);
+            self.interval *= 2;
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::intcode::{CPUExceptionKind, IntcodeCPU};
+
+    fn run_to_exception(program: Vec<i32>) -> (CPUExceptionKind, u64) {
+        let mut cpu = IntcodeCPU::new(program);
+        cpu.enable_loop_detection();
+        cpu.set_step_limit(Some(10_000));
+
+        let ex = cpu.run().expect_err("Program should not halt");
+        (ex.kind(), cpu.steps())
+    }
+
+    #[test]
+    fn detects_jump_to_self() {
+        let (kind, _) = run_to_exception(vec![1105, 1, 0]);
+
+        assert!(matches!(kind, CPUExceptionKind::InfiniteLoop));
+    }
+
+    #[test]
+    fn detects_cycle_through_memory() {
+        // [7] flips between 1 and -1, so the state repeats every 4 steps
+        let (kind, steps) = run_to_exception(vec![1002, 7, -1, 7, 1105, 1, 0, 1]);
+
+        assert!(matches!(kind, CPUExceptionKind::InfiniteLoop));
+        assert!(steps < 16);
+    }
+
+    #[test]
+    fn counter_is_not_a_loop() {
+        let (kind, steps) = run_to_exception(vec![1001, 7, 1, 7, 1105, 1, 0, 0]);
+
+        assert!(matches!(kind, CPUExceptionKind::StepLimitExceeded));
+        assert_eq!(steps, 10_000);
+    }
+}
@@ -0,0 +1,168 @@
+use std::fmt;
+use std::rc::Rc;
+
+/// Number of cells in each page of `Memory`
+pub const PAGE_SIZE: usize = 64;
+
+type Page = [i32; PAGE_SIZE];
+
+/// Intcode memory split into fixed-size pages that are shared between
+/// clones and copied on first write, so forking a CPU only costs a page
+/// table and each fork pays for the pages it actually changes.
+#[derive(Clone, Default)]
+pub struct Memory {
+    pages: Vec<Rc<Page>>,
+    len: usize,
+}
+
+impl Memory {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, pos: usize) -> Option<i32> {
+        if pos < self.len {
+            Some(self.pages[pos / PAGE_SIZE][pos % PAGE_SIZE])
+        } else {
+            None
+        }
+    }
+
+    /// Stores `val` at `pos`, returning the old value, or `None` if `pos` is
+    /// out of bounds. A page still shared with another clone is copied.
+    pub fn set(&mut self, pos: usize, val: i32) -> Option<i32> {
+        if pos >= self.len {
+            return None;
+        }
+
+        let page = Rc::make_mut(&mut self.pages[pos / PAGE_SIZE]);
+        Some(std::mem::replace(&mut page[pos % PAGE_SIZE], val))
+    }
+
+    /// Grows or shrinks memory to `len` cells, filling new cells with `val`
+    pub fn resize(&mut self, len: usize, val: i32) {
+        if len < self.len {
+            self.pages.truncate(len.div_ceil(PAGE_SIZE));
+            // Cells past the end are kept zeroed so that growing again
+            // can't resurrect old values
+            for pos in len..(self.pages.len() * PAGE_SIZE) {
+                Rc::make_mut(&mut self.pages[pos / PAGE_SIZE])[pos % PAGE_SIZE] = 0;
+            }
+            self.len = len;
+            return;
+        }
+
+        while self.pages.len() * PAGE_SIZE < len {
+            self.pages.push(Rc::new([0; PAGE_SIZE]));
+        }
+        if val != 0 {
+            for pos in self.len..len {
+                Rc::make_mut(&mut self.pages[pos / PAGE_SIZE])[pos % PAGE_SIZE] = val;
+            }
+        }
+        self.len = len;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = i32> + '_ {
+        self.pages
+            .iter()
+            .flat_map(|page| page.iter())
+            .take(self.len)
+            .copied()
+    }
+
+    pub fn to_vec(&self) -> Vec<i32> {
+        self.iter().collect()
+    }
+
+    /// Number of pages this memory still shares with `other`
+    pub fn shared_pages(&self, other: &Memory) -> usize {
+        self.pages
+            .iter()
+            .zip(&other.pages)
+            .filter(|(a, b)| Rc::ptr_eq(a, b))
+            .count()
+    }
+}
+
+impl From<&[i32]> for Memory {
+    fn from(cells: &[i32]) -> Self {
+        let pages = cells
+            .chunks(PAGE_SIZE)
+            .map(|chunk| {
+                let mut page = [0; PAGE_SIZE];
+                page[..chunk.len()].copy_from_slice(chunk);
+                Rc::new(page)
+            })
+            .collect();
+
+        Memory {
+            pages,
+            len: cells.len(),
+        }
+    }
+}
+
+impl From<Vec<i32>> for Memory {
+    fn from(cells: Vec<i32>) -> Self {
+        Memory::from(&cells[..])
+    }
+}
+
+impl PartialEq for Memory {
+    fn eq(&self, other: &Memory) -> bool {
+        // Cells past the end are always zero, so whole pages can be compared
+        self.len == other.len
+            && self
+                .pages
+                .iter()
+                .zip(&other.pages)
+                .all(|(a, b)| Rc::ptr_eq(a, b) || a[..] == b[..])
+    }
+}
+
+impl Eq for Memory {}
+
+impl fmt::Debug for Memory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clones_share_pages_until_written() {
+        let original = Memory::from((0..200).collect::<Vec<i32>>());
+        let mut fork = original.clone();
+        assert_eq!(fork.shared_pages(&original), 4);
+
+        assert_eq!(fork.set(70, -1), Some(70));
+        assert_eq!(fork.set(200, -1), None);
+        assert_eq!(fork.shared_pages(&original), 3);
+        assert_eq!((original.get(70), fork.get(70)), (Some(70), Some(-1)));
+        assert_ne!(fork, original);
+
+        fork.set(70, 70);
+        assert_eq!(fork, original);
+        assert_eq!(fork.to_vec(), (0..200).collect::<Vec<i32>>());
+    }
+
+    #[test]
+    fn resize_fills_and_clears_cells() {
+        let mut memory = Memory::from(vec![1, 2, 3]);
+        memory.resize(5, 7);
+        assert_eq!(memory.to_vec(), vec![1, 2, 3, 7, 7]);
+
+        memory.resize(2, 0);
+        memory.resize(4, 0);
+        assert_eq!(memory.to_vec(), vec![1, 2, 0, 0]);
+        assert_eq!(memory, Memory::from(vec![1, 2, 0, 0]));
+    }
+}
@@ -0,0 +1,1046 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+use std::rc::Rc;
+use std::str::FromStr;
+
+pub mod cfg;
+pub mod conformance;
+pub mod coverage;
+pub mod devices;
+pub mod diff;
+pub mod display;
+pub mod engine;
+pub mod explore;
+pub mod io;
+pub mod joystick;
+pub mod loader;
+pub mod lockstep;
+mod loop_detector;
+pub mod memory;
+pub mod observer;
+pub mod profile;
+pub mod robot;
+pub mod search;
+pub mod symbolic;
+pub mod taint;
+pub mod trap;
+
+use cfg::ControlFlowGraph;
+use coverage::{CellUsage, Coverage};
+use devices::{Device, SharedDevice};
+use io::{IntcodeIO, StdIO};
+use loop_detector::LoopDetector;
+use memory::Memory;
+use observer::{Instruction, NoObserver, Observer};
+use profile::Profile;
+use taint::TaintTracker;
+use trap::{Trap, TrapAction, TrapHandler};
+
+#[derive(Copy, Clone)]
+enum Operand {
+    Position(usize),
+    Immediate(i32),
+}
+
+impl Operand {
+    fn new(mode: char, value: i32) -> CPUResult<Operand> {
+        match mode {
+            '0' => Ok(Operand::Position(value as usize)),
+            '1' => Ok(Operand::Immediate(value)),
+            _ => Err(CPUException::invalid_operand(mode)),
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Operand::Position(idx) => write!(f, "[{}]", idx),
+            Operand::Immediate(val) => write!(f, "{}", val),
+        }
+    }
+}
+
+enum CPUOp {
+    Add {
+        src1: Operand,
+        src2: Operand,
+        dst: usize,
+    },
+    Mul {
+        src1: Operand,
+        src2: Operand,
+        dst: usize,
+    },
+    Halt,
+    Input(usize),
+    Output(Operand),
+    JumpZero {
+        cmp: Operand,
+        to: Operand,
+    },
+    JumpNonZero {
+        cmp: Operand,
+        to: Operand,
+    },
+    CompareLess {
+        cmp1: Operand,
+        cmp2: Operand,
+        dst: usize,
+    },
+    CompareEqual {
+        cmp1: Operand,
+        cmp2: Operand,
+        dst: usize,
+    },
+    Undefined(i32),
+}
+
+impl CPUOp {
+    /// Decodes the instruction at `pc`, using `fetch` to read memory cells.
+    /// This is shared between the CPU and the static analysis passes.
+    ///
+    /// Destination operands must be in position mode unless `lenient` is
+    /// set, in which case their mode digit is ignored.
+    fn decode<F>(pc: usize, fetch: F, lenient: bool) -> CPUResult<CPUOp>
+    where
+        F: Fn(usize) -> Option<i32>,
+    {
+        let param = |offset: usize, ident: &str| {
+            fetch(pc + offset).ok_or_else(|| CPUException::out_of_bounds(ident, pc + offset))
+        };
+
+        let opcode = param(0, "FETCH!OP")?;
+
+        if opcode < 0 {
+            return Err(CPUException::invalid_opcode(opcode));
+        }
+
+        let opcode_str = format!("{:05}", opcode);
+
+        let (operand_modes, op) = opcode_str.split_at(3);
+        let operand_modes = operand_modes.chars().rev().collect::<Vec<char>>();
+
+        let dst = |idx: usize, ident: &str| {
+            let pos = param(idx + 1, ident)? as usize;
+            match operand_modes[idx] {
+                '0' => Ok(pos),
+                _ if lenient => Ok(pos),
+                '1' => Err(CPUException::immediate_destination(ident, pc)),
+                mode => Err(CPUException::invalid_operand(mode)),
+            }
+        };
+
+        match op {
+            "01" => Ok(CPUOp::Add {
+                src1: Operand::new(operand_modes[0], param(1, "FETCH!ADD.src1")?)?,
+                src2: Operand::new(operand_modes[1], param(2, "FETCH!ADD.src2")?)?,
+                dst: dst(2, "FETCH!ADD.dst")?,
+            }),
+            "02" => Ok(CPUOp::Mul {
+                src1: Operand::new(operand_modes[0], param(1, "FETCH!MUL.src1")?)?,
+                src2: Operand::new(operand_modes[1], param(2, "FETCH!MUL.src2")?)?,
+                dst: dst(2, "FETCH!MUL.dst")?,
+            }),
+            "03" => Ok(CPUOp::Input(dst(0, "FETCH!INPUT.dst")?)),
+            "04" => Ok(CPUOp::Output(Operand::new(
+                operand_modes[0],
+                param(1, "FETCH!OUTPUT.src")?,
+            )?)),
+            "05" => Ok(CPUOp::JumpNonZero {
+                cmp: Operand::new(operand_modes[0], param(1, "FETCH!JNZ.cmp")?)?,
+                to: Operand::new(operand_modes[1], param(2, "FETCH!JNZ.to")?)?,
+            }),
+            "06" => Ok(CPUOp::JumpZero {
+                cmp: Operand::new(operand_modes[0], param(1, "FETCH!JZ.cmp")?)?,
+                to: Operand::new(operand_modes[1], param(2, "FETCH!JZ.to")?)?,
+            }),
+            "07" => Ok(CPUOp::CompareLess {
+                cmp1: Operand::new(operand_modes[0], param(1, "FETCH!LT.cmp1")?)?,
+                cmp2: Operand::new(operand_modes[1], param(2, "FETCH!LT.cmp1")?)?,
+                dst: dst(2, "FETCH!LT.dst")?,
+            }),
+            "08" => Ok(CPUOp::CompareEqual {
+                cmp1: Operand::new(operand_modes[0], param(1, "FETCH!EQ.cmp1")?)?,
+                cmp2: Operand::new(operand_modes[1], param(2, "FETCH!EQ.cmp1")?)?,
+                dst: dst(2, "FETCH!EQ.dst")?,
+            }),
+            "99" => Ok(CPUOp::Halt),
+            undef_op => Ok(CPUOp::Undefined(i32::from_str(undef_op).unwrap())),
+        }
+    }
+
+    fn mnemonic(&self) -> &'static str {
+        match *self {
+            CPUOp::Add { .. } => "ADD",
+            CPUOp::Mul { .. } => "MUL",
+            CPUOp::Halt => "HALT",
+            CPUOp::Input(_) => "INPUT",
+            CPUOp::Output(_) => "OUTPUT",
+            CPUOp::JumpZero { .. } => "JZ",
+            CPUOp::JumpNonZero { .. } => "JNZ",
+            CPUOp::CompareLess { .. } => "LT",
+            CPUOp::CompareEqual { .. } => "EQ",
+            CPUOp::Undefined(_) => "UNDEFINED",
+        }
+    }
+
+    fn next_pc_offset(&self) -> usize {
+        match *self {
+            CPUOp::Add { .. }
+            | CPUOp::Mul { .. }
+            | CPUOp::CompareEqual { .. }
+            | CPUOp::CompareLess { .. } => 4,
+            CPUOp::JumpZero { .. } | CPUOp::JumpNonZero { .. } => 3,
+            CPUOp::Input(_) | CPUOp::Output(_) => 2,
+            CPUOp::Halt | CPUOp::Undefined { .. } => 0,
+        }
+    }
+}
+
+impl fmt::Display for CPUOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CPUOp::Add { src1, src2, dst } => write!(f, "ADD {}, {} -> [{}]", src1, src2, dst),
+            CPUOp::Mul { src1, src2, dst } => write!(f, "MUL {}, {} -> [{}]", src1, src2, dst),
+            CPUOp::Halt => write!(f, "HALT"),
+            CPUOp::Input(dst) => write!(f, "INPUT -> [{}]", dst),
+            CPUOp::Output(src) => write!(f, "OUTPUT {}", src),
+            CPUOp::JumpZero { cmp, to } => write!(f, "JZ {}, {}", cmp, to),
+            CPUOp::JumpNonZero { cmp, to } => write!(f, "JNZ {}, {}", cmp, to),
+            CPUOp::CompareLess { cmp1, cmp2, dst } => {
+                write!(f, "LT {}, {} -> [{}]", cmp1, cmp2, dst)
+            }
+            CPUOp::CompareEqual { cmp1, cmp2, dst } => {
+                write!(f, "EQ {}, {} -> [{}]", cmp1, cmp2, dst)
+            }
+            CPUOp::Undefined(opcode) => write!(f, "UNDEFINED {}", opcode),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CPUState {
+    Running,
+    Halted,
+    /// Stopped at an `Input` instruction with nothing to read
+    AwaitingInput,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum CPUExceptionKind {
+    InvalidOpcode,
+    InvalidOperand,
+    InvalidInput,
+    OutOfBounds,
+    /// An `Add` or `Mul` result doesn't fit in an `i32`
+    Overflow,
+    StepLimitExceeded,
+    InfiniteLoop,
+    WriteProtected,
+}
+
+#[derive(Clone, Debug)]
+pub struct CPUException {
+    kind: CPUExceptionKind,
+    message: String,
+    address: Option<usize>,
+}
+
+impl CPUException {
+    pub fn new(kind: CPUExceptionKind, message: String) -> Self {
+        CPUException {
+            kind,
+            message,
+            address: None,
+        }
+    }
+
+    pub fn out_of_bounds(ident: &str, pos: usize) -> Self {
+        CPUException {
+            kind: CPUExceptionKind::OutOfBounds,
+            message: format!("{}: pos {} is outside program bounds", ident, pos),
+            address: Some(pos),
+        }
+    }
+
+    pub fn invalid_opcode(opcode: i32) -> Self {
+        CPUException {
+            kind: CPUExceptionKind::InvalidOpcode,
+            message: format!("Invalid opcode {}", opcode),
+            address: None,
+        }
+    }
+
+    pub fn invalid_operand(operand: char) -> Self {
+        CPUException {
+            kind: CPUExceptionKind::InvalidOperand,
+            message: format!("Invalid operand mode {}", operand),
+            address: None,
+        }
+    }
+
+    pub fn immediate_destination(ident: &str, pc: usize) -> Self {
+        CPUException {
+            kind: CPUExceptionKind::InvalidOperand,
+            message: format!(
+                "{}: destination of instruction at {} is in immediate mode",
+                ident, pc
+            ),
+            address: None,
+        }
+    }
+
+    pub fn overflow(ident: &str, pc: usize) -> Self {
+        CPUException {
+            kind: CPUExceptionKind::Overflow,
+            message: format!("{}: result of instruction at {} overflows i32", ident, pc),
+            address: None,
+        }
+    }
+
+    pub fn step_limit_exceeded(limit: u64) -> Self {
+        CPUException {
+            kind: CPUExceptionKind::StepLimitExceeded,
+            message: format!("Program did not halt within {} steps", limit),
+            address: None,
+        }
+    }
+
+    pub fn infinite_loop(pc: usize, period: u64) -> Self {
+        CPUException {
+            kind: CPUExceptionKind::InfiniteLoop,
+            message: format!(
+                "State at pc {} repeats every {} steps without reading input",
+                pc, period
+            ),
+            address: None,
+        }
+    }
+
+    pub fn write_protected(ident: &str, pos: usize, pc: usize) -> Self {
+        CPUException {
+            kind: CPUExceptionKind::WriteProtected,
+            message: format!(
+                "{}: pos {} is write-protected, written by instruction at {}",
+                ident, pos, pc
+            ),
+            address: Some(pos),
+        }
+    }
+
+    pub fn kind(&self) -> CPUExceptionKind {
+        self.kind
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// The memory address being accessed, for exceptions caused by one
+    pub fn address(&self) -> Option<usize> {
+        self.address
+    }
+}
+
+impl fmt::Display for CPUException {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}: {}", self.kind, self.message)
+    }
+}
+
+pub type CPUResult<T> = Result<T, CPUException>;
+
+/// How many times in a row a trap handler may ask to retry a step before
+/// its exception is returned anyway
+const MAX_CONSECUTIVE_TRAPS: usize = 16;
+
+/// An Intcode machine. Cloning is cheap: memory pages are shared until
+/// written, while attached devices and trap handlers are shared outright.
+/// Use `fork` for a copy whose devices are its own.
+#[derive(Clone)]
+pub struct IntcodeCPU<IO: IntcodeIO = StdIO, O: Observer = NoObserver> {
+    program: Memory,
+    state: CPUState,
+    pc: usize,
+    io: IO,
+    steps: u64,
+    step_limit: Option<u64>,
+    loop_detector: Option<LoopDetector>,
+    coverage: Option<Coverage>,
+    profile: Option<Profile>,
+    taint: Option<TaintTracker>,
+    write_protected: Vec<Range<usize>>,
+    lenient_operands: bool,
+    devices: Vec<(Range<usize>, SharedDevice)>,
+    device_accessed: bool,
+    trap_handlers: HashMap<CPUExceptionKind, Rc<RefCell<TrapHandler>>>,
+    observer: O,
+}
+
+impl<IO: IntcodeIO + Clone, O: Observer + Clone> IntcodeCPU<IO, O> {
+    /// A clone with its own copy of every attached device, so running it
+    /// can't change what this CPU reads from them
+    pub fn fork(&self) -> Self {
+        let mut fork = self.clone();
+        for (_, device) in &mut fork.devices {
+            let copy = device.borrow().fork();
+            *device = copy;
+        }
+        fork
+    }
+}
+
+impl IntcodeCPU {
+    pub fn new(program: Vec<i32>) -> Self {
+        IntcodeCPU::with_io(program, StdIO)
+    }
+}
+
+impl<IO: IntcodeIO> IntcodeCPU<IO> {
+    pub fn with_io(program: Vec<i32>, io: IO) -> Self {
+        IntcodeCPU {
+            program: Memory::from(program),
+            state: CPUState::Running,
+            pc: 0,
+            io,
+            steps: 0,
+            step_limit: None,
+            loop_detector: None,
+            coverage: None,
+            profile: None,
+            taint: None,
+            write_protected: vec![],
+            lenient_operands: false,
+            devices: vec![],
+            device_accessed: false,
+            trap_handlers: HashMap::new(),
+            observer: NoObserver,
+        }
+    }
+}
+
+impl<IO: IntcodeIO, O: Observer> IntcodeCPU<IO, O> {
+    /// Replaces the observer, which is told about every fetch, decode,
+    /// memory access, I/O, jump, halt and exception from now on
+    pub fn with_observer<O2: Observer>(self, observer: O2) -> IntcodeCPU<IO, O2> {
+        IntcodeCPU {
+            program: self.program,
+            state: self.state,
+            pc: self.pc,
+            io: self.io,
+            steps: self.steps,
+            step_limit: self.step_limit,
+            loop_detector: self.loop_detector,
+            coverage: self.coverage,
+            profile: self.profile,
+            taint: self.taint,
+            write_protected: self.write_protected,
+            lenient_operands: self.lenient_operands,
+            devices: self.devices,
+            device_accessed: self.device_accessed,
+            trap_handlers: self.trap_handlers,
+            observer,
+        }
+    }
+
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    pub fn observer_mut(&mut self) -> &mut O {
+        &mut self.observer
+    }
+
+    pub fn io(&self) -> &IO {
+        &self.io
+    }
+
+    pub fn io_mut(&mut self) -> &mut IO {
+        &mut self.io
+    }
+
+    /// Raise `StepLimitExceeded` instead of executing more than `limit`
+    /// instructions in total
+    pub fn set_step_limit(&mut self, limit: Option<u64>) {
+        self.step_limit = limit;
+    }
+
+    /// Raise `InfiniteLoop` as soon as the pc and memory return to an
+    /// earlier state with no input read in between. This costs a copy of
+    /// memory on every checkpoint, so it is off by default.
+    pub fn enable_loop_detection(&mut self) {
+        self.loop_detector = Some(LoopDetector::new(self.pc, &self.program, self.steps));
+    }
+
+    /// Start recording which cells are executed, read and written
+    pub fn enable_coverage(&mut self) {
+        self.coverage = Some(Coverage::new(self.program.len()));
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Start counting executed instructions by opcode and by address
+    pub fn enable_profiling(&mut self) {
+        self.profile = Some(Profile::new(self.program.len()));
+    }
+
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    /// Start tracking which inputs, and which of the `seeds` cells' initial
+    /// values, each cell and output is derived from
+    pub fn enable_taint_tracking(&mut self, seeds: &[usize]) {
+        self.taint = Some(TaintTracker::new(self.program.len(), seeds));
+    }
+
+    pub fn taint(&self) -> Option<&TaintTracker> {
+        self.taint.as_ref()
+    }
+
+    /// Ignore the mode of destination operands rather than raising
+    /// `InvalidOperand` when one is in immediate mode
+    pub fn set_lenient_operands(&mut self, lenient: bool) {
+        self.lenient_operands = lenient;
+    }
+
+    /// Make the instructions reachable in the current image read-only, so
+    /// that any write into them raises `WriteProtected`. Programs that
+    /// legitimately patch their own code will fault under this, and code
+    /// beyond a patched instruction isn't found; see `protect_code`.
+    pub fn enable_write_protection(&mut self) {
+        let cfg = ControlFlowGraph::build(&self.program.to_vec());
+        for block in cfg.blocks() {
+            self.protect_range(block.start()..block.end());
+        }
+    }
+
+    /// Make every cell executed in any of the recorded `runs` read-only,
+    /// except cells that a run both executed and wrote to, which the
+    /// program patches on purpose. A write into code that only another run
+    /// executed still raises `WriteProtected`.
+    pub fn protect_code(&mut self, runs: &[&Coverage]) {
+        let protected = |pos: usize| {
+            let cells = runs.iter().filter_map(|run| run.cell(pos));
+            cells.clone().any(CellUsage::is_code) && !cells.clone().any(CellUsage::is_self_modified)
+        };
+
+        let mut start = None;
+        for pos in 0..=self.program.len() {
+            match (start, pos < self.program.len() && protected(pos)) {
+                (None, true) => start = Some(pos),
+                (Some(from), false) => {
+                    self.protect_range(from..pos);
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Raise `WriteProtected` on any write to a cell in `range`
+    pub fn protect_range(&mut self, range: Range<usize>) {
+        if !range.is_empty() {
+            self.write_protected.push(range);
+        }
+    }
+
+    /// Map `device` at the addresses in `range`, which may lie beyond the end
+    /// of memory. Reads and writes there go to the device instead of memory;
+    /// instructions are never fetched from it. Where ranges overlap, the
+    /// device attached first wins.
+    pub fn attach_device<D: Device + 'static>(
+        &mut self,
+        range: Range<usize>,
+        device: Rc<RefCell<D>>,
+    ) {
+        self.devices.push((range, device));
+    }
+
+    fn device_at(&self, pos: usize) -> Option<(usize, SharedDevice)> {
+        self.devices
+            .iter()
+            .find(|(range, _)| range.contains(&pos))
+            .map(|(range, device)| (pos - range.start, device.clone()))
+    }
+
+    /// Record the instruction at pc computing `dst` from `srcs`, where the
+    /// operands are fetched from the cells following the opcode in order
+    fn propagate_taint(&mut self, srcs: &[Operand], dst: usize) {
+        let pc = self.pc;
+
+        if let Some(taint) = &mut self.taint {
+            let srcs = srcs
+                .iter()
+                .enumerate()
+                .map(|(i, &oper)| (oper, pc + 1 + i))
+                .collect::<Vec<_>>();
+            taint.propagate(&srcs, dst, pc + 1 + srcs.len());
+        }
+    }
+
+    fn read(&mut self, pos: usize, ident: &str) -> CPUResult<i32> {
+        if let Some((offset, device)) = self.device_at(pos) {
+            self.device_accessed = true;
+            return device.borrow_mut().read(offset, self.steps);
+        }
+
+        let val = self
+            .program
+            .get(pos)
+            .ok_or_else(|| CPUException::out_of_bounds(ident, pos))?;
+
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record_read(pos);
+        }
+
+        self.observer.read(pos, val);
+
+        Ok(val)
+    }
+
+    /// Raises whatever writing to memory at `pos` would, without writing.
+    /// Mapped devices are only asked when actually written to.
+    fn check_write(&self, pos: usize, ident: &str) -> CPUResult<()> {
+        if self.device_at(pos).is_some() {
+            return Ok(());
+        }
+
+        if self
+            .write_protected
+            .iter()
+            .any(|range| range.contains(&pos))
+        {
+            return Err(CPUException::write_protected(ident, pos, self.pc));
+        }
+
+        if pos >= self.program.len() {
+            return Err(CPUException::out_of_bounds(ident, pos));
+        }
+
+        Ok(())
+    }
+
+    fn write(&mut self, pos: usize, val: i32, ident: &str) -> CPUResult<()> {
+        if let Some((offset, device)) = self.device_at(pos) {
+            self.device_accessed = true;
+            return device.borrow_mut().write(offset, val, self.steps);
+        }
+
+        self.check_write(pos, ident)?;
+        let old = self
+            .program
+            .set(pos, val)
+            .ok_or_else(|| CPUException::out_of_bounds(ident, pos))?;
+
+        if let Some(detector) = &mut self.loop_detector {
+            detector.record_write(pos, old, val);
+        }
+
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record_write(pos);
+        }
+
+        self.observer.write(pos, old, val);
+
+        Ok(())
+    }
+
+    fn get_operand_value(&mut self, oper: Operand, ident: &str) -> CPUResult<i32> {
+        use Operand::*;
+
+        match oper {
+            Position(idx) => self.read(idx, ident),
+            Immediate(val) => Ok(val),
+        }
+    }
+
+    fn execute_op(&mut self, op: CPUOp) -> CPUResult<()> {
+        let offset = op.next_pc_offset();
+        match op {
+            CPUOp::Add { src1, src2, dst } => {
+                let src1_val = self.get_operand_value(src1, "EXEC!ADD.src1")?;
+                let src2_val = self.get_operand_value(src2, "EXEC!ADD.src2")?;
+                let sum = src1_val
+                    .checked_add(src2_val)
+                    .ok_or_else(|| CPUException::overflow("EXEC!ADD", self.pc))?;
+                self.write(dst, sum, "EXEC!ADD.dst")?;
+                self.propagate_taint(&[src1, src2], dst);
+            }
+            CPUOp::Mul { src1, src2, dst } => {
+                let src1_val = self.get_operand_value(src1, "EXEC!MUL.src1")?;
+                let src2_val = self.get_operand_value(src2, "EXEC!MUL.src2")?;
+                let product = src1_val
+                    .checked_mul(src2_val)
+                    .ok_or_else(|| CPUException::overflow("EXEC!MUL", self.pc))?;
+                self.write(dst, product, "EXEC!MUL.dst")?;
+                self.propagate_taint(&[src1, src2], dst);
+            }
+            CPUOp::Halt => {
+                self.state = CPUState::Halted;
+                self.observer.halt(self.pc);
+            }
+            CPUOp::Input(dst) => {
+                // Check before reading, so that an input is never consumed
+                // only to be dropped, e.g. by a trap handler retrying
+                self.check_write(dst, "EXEC!INPUT.dst")?;
+
+                let input = match self.io.input()? {
+                    Some(input) => input,
+                    None => {
+                        self.state = CPUState::AwaitingInput;
+                        return Ok(());
+                    }
+                };
+                self.state = CPUState::Running;
+
+                self.write(dst, input, "EXEC!INPUT.dst")?;
+                self.observer.input(dst, input);
+
+                if let Some(taint) = &mut self.taint {
+                    taint.record_input(dst, self.pc + 1);
+                }
+
+                if let Some(detector) = &mut self.loop_detector {
+                    detector.record_input(self.pc + offset, &self.program, self.steps + 1);
+                }
+            }
+            CPUOp::JumpZero { cmp, to } => {
+                let cmp = self.get_operand_value(cmp, "EXEC!JZ.cmp")?;
+                let to = self.get_operand_value(to, "EXEC!JZ.to")? as usize;
+
+                if cmp == 0 {
+                    self.observer.jump(self.pc, to);
+                    self.pc = to;
+                    return Ok(());
+                }
+            }
+            CPUOp::JumpNonZero { cmp, to } => {
+                let cmp = self.get_operand_value(cmp, "EXEC!JNZ.cmp")?;
+                let to = self.get_operand_value(to, "EXEC!JNZ.to")? as usize;
+
+                if cmp != 0 {
+                    self.observer.jump(self.pc, to);
+                    self.pc = to;
+                    return Ok(());
+                }
+            }
+            CPUOp::CompareEqual { cmp1, cmp2, dst } => {
+                let cmp1_val = self.get_operand_value(cmp1, "EXEC!EQ.cmp1")?;
+                let cmp2_val = self.get_operand_value(cmp2, "EXEC!EQ.cmp2")?;
+                self.write(dst, i32::from(cmp1_val == cmp2_val), "EXEC!EQ.dst")?;
+                self.propagate_taint(&[cmp1, cmp2], dst);
+            }
+            CPUOp::CompareLess { cmp1, cmp2, dst } => {
+                let cmp1_val = self.get_operand_value(cmp1, "EXEC!LT.cmp1")?;
+                let cmp2_val = self.get_operand_value(cmp2, "EXEC!LT.cmp2")?;
+                self.write(dst, i32::from(cmp1_val < cmp2_val), "EXEC!LT.dst")?;
+                self.propagate_taint(&[cmp1, cmp2], dst);
+            }
+            CPUOp::Output(src) => {
+                let val = self.get_operand_value(src, "EXEC!OUTPUT.src")?;
+                self.io.output(val);
+                self.observer.output(val);
+
+                if let Some(taint) = &mut self.taint {
+                    taint.record_output(src, self.pc + 1);
+                }
+            }
+            CPUOp::Undefined(opcode) => return Err(CPUException::invalid_opcode(opcode)),
+        }
+
+        self.pc += offset;
+        Ok(())
+    }
+
+    /// The cells of a `len` long instruction at `pc`, at least the opcode
+    fn fetch_cells(&self, pc: usize, len: usize) -> Vec<i32> {
+        (pc..pc + len.max(1))
+            .map_while(|pos| self.program.get(pos))
+            .collect()
+    }
+
+    fn fetch_op(&self) -> CPUResult<CPUOp> {
+        CPUOp::decode(
+            self.pc,
+            |addr| self.program.get(addr),
+            self.lenient_operands,
+        )
+    }
+
+    /// Calls `handler` instead of returning exceptions of `kind` from
+    /// `step()`, replacing any handler already set for it
+    pub fn set_trap_handler(&mut self, kind: CPUExceptionKind, handler: TrapHandler) {
+        self.trap_handlers
+            .insert(kind, Rc::new(RefCell::new(handler)));
+    }
+
+    pub fn clear_trap_handler(&mut self, kind: CPUExceptionKind) {
+        self.trap_handlers.remove(&kind);
+    }
+
+    /// Executes a single instruction. An exception with a trap handler set
+    /// for its kind is passed to the handler, which decides whether it
+    /// ends the step.
+    pub fn step(&mut self) -> CPUResult<CPUState> {
+        let mut traps = 0;
+        loop {
+            traps += 1;
+            let ex = match self.step_inner() {
+                Ok(state) => return Ok(state),
+                Err(ex) => ex,
+            };
+            self.observer.exception(self.pc, &ex);
+
+            let handler = match self.trap_handlers.get(&ex.kind()) {
+                Some(handler) => Rc::clone(handler),
+                None => return Err(ex),
+            };
+            let action =
+                (handler.borrow_mut())(&mut Trap::new(&ex, &mut self.pc, &mut self.program));
+
+            // The handler may have patched memory behind the detector's back
+            if self.loop_detector.is_some() {
+                self.enable_loop_detection();
+            }
+
+            match action {
+                TrapAction::Retry if traps < MAX_CONSECUTIVE_TRAPS => continue,
+                TrapAction::Abort | TrapAction::Retry => return Err(ex),
+                TrapAction::Resume => {
+                    let len = self.fetch_op().map_or(1, |op| op.next_pc_offset().max(1));
+                    // A negative jump target leaves the pc near usize::MAX
+                    return match self.pc.checked_add(len) {
+                        Some(pc) => {
+                            self.pc = pc;
+                            Ok(self.state)
+                        }
+                        None => Err(ex),
+                    };
+                }
+            }
+        }
+    }
+
+    fn step_inner(&mut self) -> CPUResult<CPUState> {
+        if let Some(limit) = self.step_limit {
+            if self.steps >= limit {
+                return Err(CPUException::step_limit_exceeded(limit));
+            }
+        }
+
+        if let Some(detector) = &mut self.loop_detector {
+            if let Some(period) = detector.check(self.pc, &self.program, self.steps) {
+                return Err(CPUException::infinite_loop(self.pc, period));
+            }
+        }
+
+        let pc = self.pc;
+        if let Some(opcode) = self.program.get(pc) {
+            self.observer.fetch(pc, opcode);
+        }
+        let op = self.fetch_op()?;
+        self.observer.decode(pc, &Instruction::new(&op));
+        let (mnemonic, len) = (op.mnemonic(), op.next_pc_offset());
+        // Coverage wants the code as fetched, before it can overwrite itself
+        let code = self.coverage.as_ref().map(|_| self.fetch_cells(pc, len));
+
+        self.execute_op(op)?;
+
+        if self.state == CPUState::AwaitingInput {
+            return Ok(self.state);
+        }
+
+        // Devices are external state, so like input they make a repeated
+        // memory state no proof of a loop
+        if std::mem::take(&mut self.device_accessed) {
+            if let Some(detector) = &mut self.loop_detector {
+                detector.record_input(self.pc, &self.program, self.steps + 1);
+            }
+        }
+
+        self.steps += 1;
+
+        if let (Some(coverage), Some(code)) = (&mut self.coverage, code) {
+            coverage.record_execute(pc, &code);
+        }
+
+        if let Some(profile) = &mut self.profile {
+            profile.record(pc, mnemonic);
+        }
+
+        Ok(self.state)
+    }
+
+    /// Runs until the program halts or needs input that isn't available
+    pub fn run(&mut self) -> CPUResult<CPUState> {
+        loop {
+            match self.step()? {
+                CPUState::Running => continue,
+                state => return Ok(state),
+            }
+        }
+    }
+
+    pub fn state(&self) -> CPUState {
+        self.state
+    }
+
+    /// Disassembly of the instruction at pc
+    pub fn current_instruction(&self) -> CPUResult<String> {
+        self.fetch_op().map(|op| op.to_string())
+    }
+
+    pub fn get_position(&self, pos: usize) -> Option<i32> {
+        self.program.get(pos)
+    }
+
+    /// Stores `val` at `pos`, returning the old value, or `None` if `pos`
+    /// is outside memory
+    pub fn set_position(&mut self, pos: usize, val: i32) -> Option<i32> {
+        let old = self.program.set(pos, val)?;
+        if let Some(detector) = &mut self.loop_detector {
+            detector.record_write(pos, old, val);
+        }
+        Some(old)
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    /// Number of instructions executed so far
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    pub fn output(&self) -> i32 {
+        self.program
+            .get(0)
+            .expect("Output (pos 0) not found in program")
+    }
+
+    /// noun = input 1 in challenge parlance
+    pub fn noun(&self) -> i32 {
+        self.program
+            .get(1)
+            .expect("Noun (pos 1) not found in program")
+    }
+
+    /// verb = input 2 in challenge parlance
+    pub fn verb(&self) -> i32 {
+        self.program
+            .get(2)
+            .expect("Verb (pos 2) not found in program")
+    }
+
+    /// A copy of memory as a flat image
+    pub fn inspect_state(&self) -> Vec<i32> {
+        self.program.to_vec()
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.program
+    }
+
+    /// Consumes the CPU, handing back its memory for reuse
+    pub fn into_memory(self) -> Vec<i32> {
+        self.program.to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use io::QueueIO;
+
+    #[test]
+    fn clones_run_independently() {
+        let mut cpu = IntcodeCPU::with_io(vec![3, 5, 4, 5, 99, 0], QueueIO::default());
+        assert_eq!(cpu.run().unwrap(), CPUState::AwaitingInput);
+
+        let mut fork = cpu.clone();
+        fork.io_mut().push_input(7);
+        assert_eq!(fork.run().unwrap(), CPUState::Halted);
+        assert_eq!(fork.io().outputs(), &[7]);
+        assert_eq!(fork.memory().shared_pages(cpu.memory()), 0);
+
+        cpu.io_mut().push_input(8);
+        assert_eq!(cpu.run().unwrap(), CPUState::Halted);
+        assert_eq!(cpu.io().outputs(), &[8]);
+        assert_eq!(fork.into_memory(), vec![3, 5, 4, 5, 99, 7]);
+        assert_eq!(cpu.into_memory(), vec![3, 5, 4, 5, 99, 8]);
+    }
+
+    #[test]
+    fn write_into_code_is_rejected() {
+        let program = vec![1, 0, 0, 0, 99];
+        let mut cpu = IntcodeCPU::with_io(program, QueueIO::default());
+        cpu.enable_write_protection();
+
+        let ex = cpu.run().expect_err("Should have excepted at runtime");
+        assert!(matches!(ex.kind(), CPUExceptionKind::WriteProtected));
+        assert_eq!(cpu.pc(), 0);
+        assert_eq!(cpu.inspect_state()[0], 1);
+    }
+
+    #[test]
+    fn writes_outside_protected_ranges_are_allowed() {
+        let program = vec![1, 5, 6, 7, 99, 20, 22, 0];
+        let mut cpu = IntcodeCPU::with_io(program.clone(), QueueIO::default());
+        cpu.enable_write_protection();
+        cpu.run().expect("Should not have excepted at runtime");
+        assert_eq!(cpu.inspect_state()[7], 42);
+
+        let mut cpu = IntcodeCPU::with_io(program, QueueIO::default());
+        cpu.protect_range(6..8);
+        let ex = cpu.run().expect_err("Should have excepted at runtime");
+        assert!(matches!(ex.kind(), CPUExceptionKind::WriteProtected));
+    }
+
+    #[test]
+    fn code_executed_by_another_run_is_protected() {
+        // Input 0 outputs 7, while input 1 overwrites that OUTPUT with 0
+        let program = vec![3, 14, 1005, 14, 9, 104, 7, 99, 0, 1101, 0, 0, 5, 99, 0];
+        let covered = |input: i32| {
+            let mut cpu = IntcodeCPU::with_io(program.clone(), QueueIO::new(vec![input]));
+            cpu.enable_coverage();
+            cpu.run().expect("Should not have excepted at runtime");
+            cpu.coverage().unwrap().clone()
+        };
+        let runs = [covered(0), covered(1)];
+
+        let mut cpu = IntcodeCPU::with_io(program.clone(), QueueIO::new(vec![0]));
+        cpu.protect_code(&[&runs[0], &runs[1]]);
+        assert_eq!(cpu.run().unwrap(), CPUState::Halted);
+
+        let mut cpu = IntcodeCPU::with_io(program, QueueIO::new(vec![1]));
+        cpu.protect_code(&[&runs[0], &runs[1]]);
+        let ex = cpu.run().expect_err("Should have excepted at runtime");
+        assert_eq!(
+            (ex.kind(), ex.address()),
+            (CPUExceptionKind::WriteProtected, Some(5))
+        );
+        assert_eq!(cpu.pc(), 9);
+    }
+
+    #[test]
+    fn immediate_destination_is_rejected_unless_lenient() {
+        let program = vec![11101, 2, 3, 5, 99, 0];
+        let mut cpu = IntcodeCPU::with_io(program.clone(), QueueIO::default());
+
+        let ex = cpu.run().expect_err("Should have excepted at runtime");
+        assert!(matches!(ex.kind(), CPUExceptionKind::InvalidOperand));
+        assert_eq!(cpu.pc(), 0);
+
+        let mut cpu = IntcodeCPU::with_io(program, QueueIO::default());
+        cpu.set_lenient_operands(true);
+        cpu.run().expect("Should not have excepted at runtime");
+        assert_eq!(cpu.inspect_state()[5], 5);
+    }
+}
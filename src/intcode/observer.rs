@@ -0,0 +1,269 @@
+use std::collections::BTreeSet;
+use std::fmt;
+
+use super::{CPUException, CPUOp};
+
+/// A decoded instruction, as seen by an `Observer`
+pub struct Instruction<'a> {
+    op: &'a CPUOp,
+}
+
+impl<'a> Instruction<'a> {
+    pub(super) fn new(op: &'a CPUOp) -> Self {
+        Instruction { op }
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
+        self.op.mnemonic()
+    }
+
+    /// Number of cells the instruction occupies
+    pub fn size(&self) -> usize {
+        self.op.next_pc_offset().max(1)
+    }
+}
+
+impl fmt::Display for Instruction<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.op.fmt(f)
+    }
+}
+
+/// Callbacks made by `IntcodeCPU` as it executes. Every method does nothing
+/// by default, so implementations only override the events they need.
+///
+/// The CPU is generic over its observer, so with the default `NoObserver`
+/// the calls compile away entirely.
+pub trait Observer {
+    /// The opcode cell at `pc` is about to be decoded
+    fn fetch(&mut self, _pc: usize, _opcode: i32) {}
+
+    fn decode(&mut self, _pc: usize, _instruction: &Instruction) {}
+
+    /// A memory cell was read as an operand. Accesses to mapped devices
+    /// aren't reported.
+    fn read(&mut self, _pos: usize, _value: i32) {}
+
+    fn write(&mut self, _pos: usize, _old: i32, _new: i32) {}
+
+    /// An `Input` instruction stored `value` at `pos`
+    fn input(&mut self, _pos: usize, _value: i32) {}
+
+    fn output(&mut self, _value: i32) {}
+
+    /// A conditional jump at `from` was taken
+    fn jump(&mut self, _from: usize, _to: usize) {}
+
+    fn halt(&mut self, _pc: usize) {}
+
+    /// A step raised an exception with the pc at `pc`
+    fn exception(&mut self, _pc: usize, _ex: &CPUException) {}
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct NoObserver;
+
+impl Observer for NoObserver {}
+
+/// Passes every event to both observers, in order
+impl<A: Observer, B: Observer> Observer for (A, B) {
+    fn fetch(&mut self, pc: usize, opcode: i32) {
+        self.0.fetch(pc, opcode);
+        self.1.fetch(pc, opcode);
+    }
+
+    fn decode(&mut self, pc: usize, instruction: &Instruction) {
+        self.0.decode(pc, instruction);
+        self.1.decode(pc, instruction);
+    }
+
+    fn read(&mut self, pos: usize, value: i32) {
+        self.0.read(pos, value);
+        self.1.read(pos, value);
+    }
+
+    fn write(&mut self, pos: usize, old: i32, new: i32) {
+        self.0.write(pos, old, new);
+        self.1.write(pos, old, new);
+    }
+
+    fn input(&mut self, pos: usize, value: i32) {
+        self.0.input(pos, value);
+        self.1.input(pos, value);
+    }
+
+    fn output(&mut self, value: i32) {
+        self.0.output(value);
+        self.1.output(value);
+    }
+
+    fn jump(&mut self, from: usize, to: usize) {
+        self.0.jump(from, to);
+        self.1.jump(from, to);
+    }
+
+    fn halt(&mut self, pc: usize) {
+        self.0.halt(pc);
+        self.1.halt(pc);
+    }
+
+    fn exception(&mut self, pc: usize, ex: &CPUException) {
+        self.0.exception(pc, ex);
+        self.1.exception(pc, ex);
+    }
+}
+
+/// Records each instruction executed as `pc: disassembly`
+#[derive(Clone, Debug, Default)]
+pub struct Trace {
+    lines: Vec<String>,
+}
+
+impl Trace {
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+
+    pub fn take_lines(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.lines)
+    }
+}
+
+impl Observer for Trace {
+    fn decode(&mut self, pc: usize, instruction: &Instruction) {
+        self.lines.push(format!("{}: {}", pc, instruction));
+    }
+
+    fn exception(&mut self, pc: usize, ex: &CPUException) {
+        self.lines.push(format!("{}: {}", pc, ex));
+    }
+}
+
+/// A memory access matching a watchpoint
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WatchHit {
+    Read { pos: usize, value: i32 },
+    Write { pos: usize, old: i32, new: i32 },
+}
+
+/// Records every read and write of the watched addresses
+#[derive(Clone, Debug, Default)]
+pub struct Watchpoints {
+    watched: BTreeSet<usize>,
+    hits: Vec<(usize, WatchHit)>,
+    pc: usize,
+}
+
+impl Watchpoints {
+    pub fn new<I: IntoIterator<Item = usize>>(addresses: I) -> Self {
+        Watchpoints {
+            watched: addresses.into_iter().collect(),
+            ..Self::default()
+        }
+    }
+
+    /// Each access with the address of the instruction that made it
+    pub fn hits(&self) -> &[(usize, WatchHit)] {
+        &self.hits
+    }
+}
+
+impl Observer for Watchpoints {
+    fn fetch(&mut self, pc: usize, _opcode: i32) {
+        self.pc = pc;
+    }
+
+    fn read(&mut self, pos: usize, value: i32) {
+        if self.watched.contains(&pos) {
+            self.hits.push((self.pc, WatchHit::Read { pos, value }));
+        }
+    }
+
+    fn write(&mut self, pos: usize, old: i32, new: i32) {
+        if self.watched.contains(&pos) {
+            self.hits.push((self.pc, WatchHit::Write { pos, old, new }));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::io::QueueIO;
+    use crate::intcode::IntcodeCPU;
+
+    #[test]
+    fn trace_and_watchpoints_observe_execution() {
+        let program = vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50];
+        let mut cpu = IntcodeCPU::with_io(program, QueueIO::default())
+            .with_observer((Trace::default(), Watchpoints::new(vec![3])));
+        cpu.run().expect("Should not have excepted at runtime");
+
+        let (trace, watch) = cpu.observer();
+        assert_eq!(
+            trace.lines(),
+            &[
+                "0: ADD [9], [10] -> [3]".to_string(),
+                "4: MUL [3], [11] -> [0]".to_string(),
+                "8: HALT".to_string(),
+            ]
+        );
+        assert_eq!(
+            watch.hits(),
+            &[
+                (
+                    0,
+                    WatchHit::Write {
+                        pos: 3,
+                        old: 3,
+                        new: 70
+                    }
+                ),
+                (4, WatchHit::Read { pos: 3, value: 70 }),
+            ]
+        );
+    }
+
+    #[derive(Default)]
+    struct Events(Vec<String>);
+
+    impl Observer for Events {
+        fn input(&mut self, pos: usize, value: i32) {
+            self.0.push(format!("input {} -> [{}]", value, pos));
+        }
+
+        fn output(&mut self, value: i32) {
+            self.0.push(format!("output {}", value));
+        }
+
+        fn jump(&mut self, from: usize, to: usize) {
+            self.0.push(format!("jump {} -> {}", from, to));
+        }
+
+        fn halt(&mut self, pc: usize) {
+            self.0.push(format!("halt {}", pc));
+        }
+
+        fn exception(&mut self, pc: usize, ex: &CPUException) {
+            self.0.push(format!("exception {} {:?}", pc, ex.kind()));
+        }
+    }
+
+    #[test]
+    fn control_and_io_events() {
+        let program = vec![3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9];
+        let mut cpu =
+            IntcodeCPU::with_io(program, QueueIO::new(vec![0])).with_observer(Events::default());
+        cpu.run().expect("Should not have excepted at runtime");
+
+        assert_eq!(
+            cpu.observer().0,
+            vec!["input 0 -> [12]", "jump 2 -> 9", "output 0", "halt 11"]
+        );
+
+        let mut cpu =
+            IntcodeCPU::with_io(vec![4, 10], QueueIO::default()).with_observer(Events::default());
+        cpu.run().expect_err("Should have excepted at runtime");
+        assert_eq!(cpu.observer().0, vec!["exception 0 OutOfBounds"]);
+    }
+}
@@ -0,0 +1,127 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Instruction counts gathered while the CPU runs.
+#[derive(Clone, Debug)]
+pub struct Profile {
+    total: u64,
+    by_opcode: BTreeMap<&'static str, u64>,
+    by_address: Vec<u64>,
+}
+
+impl Profile {
+    pub fn new(len: usize) -> Self {
+        Profile {
+            total: 0,
+            by_opcode: BTreeMap::new(),
+            by_address: vec![0; len],
+        }
+    }
+
+    pub(super) fn record(&mut self, pc: usize, mnemonic: &'static str) {
+        self.total += 1;
+        *self.by_opcode.entry(mnemonic).or_insert(0) += 1;
+        if let Some(count) = self.by_address.get_mut(pc) {
+            *count += 1;
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    pub fn opcode_count(&self, mnemonic: &str) -> u64 {
+        self.by_opcode.get(mnemonic).copied().unwrap_or(0)
+    }
+
+    pub fn address_count(&self, pc: usize) -> u64 {
+        self.by_address.get(pc).copied().unwrap_or(0)
+    }
+
+    /// Executed opcodes, most frequent first
+    pub fn opcodes(&self) -> Vec<(&'static str, u64)> {
+        let mut opcodes = self
+            .by_opcode
+            .iter()
+            .map(|(&mnemonic, &count)| (mnemonic, count))
+            .collect::<Vec<_>>();
+        opcodes.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        opcodes
+    }
+
+    /// Executed instruction addresses, most frequent first
+    pub fn hot_spots(&self) -> Vec<(usize, u64)> {
+        let mut addresses = self
+            .by_address
+            .iter()
+            .enumerate()
+            .filter(|(_, &count)| count > 0)
+            .map(|(pc, &count)| (pc, count))
+            .collect::<Vec<_>>();
+        addresses.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        addresses
+    }
+
+    /// Human readable summary listing every opcode and the `top` hottest
+    /// addresses.
+    pub fn report(&self, top: usize) -> String {
+        let mut out = String::new();
+        let percent = |count: u64| 100.0 * count as f64 / self.total.max(1) as f64;
+
+        writeln!(out, "Total steps: {}", self.total).unwrap();
+        writeln!(out, "By opcode:").unwrap();
+        for (mnemonic, count) in self.opcodes() {
+            writeln!(
+                out,
+                "  {:<8} {:>12} {:>6.2}%",
+                mnemonic,
+                count,
+                percent(count)
+            )
+            .unwrap();
+        }
+
+        writeln!(out, "Hot spots:").unwrap();
+        for (pc, count) in self.hot_spots().into_iter().take(top) {
+            writeln!(out, "  {:>8} {:>12} {:>6.2}%", pc, count, percent(count)).unwrap();
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::intcode::IntcodeCPU;
+
+    #[test]
+    fn counts_loop_iterations() {
+        let program = vec![1001, 10, -1, 10, 1005, 10, 0, 99, 0, 0, 3];
+        let mut cpu = IntcodeCPU::new(program);
+        cpu.enable_profiling();
+        cpu.run().expect("Should not have excepted at runtime");
+
+        assert_eq!(cpu.steps(), 7);
+
+        let profile = cpu.profile().unwrap();
+        assert_eq!(profile.total(), 7);
+        assert_eq!(profile.opcode_count("ADD"), 3);
+        assert_eq!(profile.opcode_count("JNZ"), 3);
+        assert_eq!(profile.opcode_count("HALT"), 1);
+        assert_eq!(profile.hot_spots(), vec![(0, 3), (4, 3), (7, 1)]);
+    }
+
+    #[test]
+    fn report_lists_hottest_first() {
+        let program = vec![1001, 10, -1, 10, 1005, 10, 0, 99, 0, 0, 3];
+        let mut cpu = IntcodeCPU::new(program);
+        cpu.enable_profiling();
+        cpu.run().expect("Should not have excepted at runtime");
+
+        let report = cpu.profile().unwrap().report(1);
+
+        assert!(report.starts_with("Total steps: 7\n"));
+        assert!(report.contains("  ADD                 3  42.86%"));
+        assert!(report.ends_with("Hot spots:\n         0            3  42.86%\n"));
+    }
+}
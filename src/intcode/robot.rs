@@ -0,0 +1,293 @@
+use std::collections::{HashMap, HashSet};
+
+use super::io::IntcodeIO;
+use super::observer::Observer;
+use super::{CPUException, CPUExceptionKind, CPUResult, CPUState, IntcodeCPU};
+
+pub const BLACK: i32 = 0;
+pub const WHITE: i32 = 1;
+
+/// Turn values the robot's program outputs
+pub const TURN_LEFT: i32 = 0;
+pub const TURN_RIGHT: i32 = 1;
+
+/// Which way the robot faces. Positions follow day03's `parse_path`: x
+/// grows to the right and y grows upwards.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Heading {
+    Up,
+    Right,
+    Down,
+    Left,
+}
+
+impl Heading {
+    pub fn turn_left(self) -> Self {
+        match self {
+            Heading::Up => Heading::Left,
+            Heading::Left => Heading::Down,
+            Heading::Down => Heading::Right,
+            Heading::Right => Heading::Up,
+        }
+    }
+
+    pub fn turn_right(self) -> Self {
+        self.turn_left().turn_left().turn_left()
+    }
+
+    /// The position one panel ahead of `pos`
+    pub fn step(self, (x, y): (i32, i32)) -> (i32, i32) {
+        match self {
+            Heading::Up => (x, y + 1),
+            Heading::Right => (x + 1, y),
+            Heading::Down => (x, y - 1),
+            Heading::Left => (x - 1, y),
+        }
+    }
+}
+
+/// An infinite grid of panels, all black unless set otherwise
+#[derive(Clone, Debug, Default)]
+pub struct Hull {
+    panels: HashMap<(i32, i32), i32>,
+    painted: HashSet<(i32, i32)>,
+}
+
+impl Hull {
+    pub fn color(&self, pos: (i32, i32)) -> i32 {
+        self.panels.get(&pos).copied().unwrap_or(BLACK)
+    }
+
+    /// Colours a panel without counting it as painted, e.g. to set up the
+    /// starting panel
+    pub fn set_color(&mut self, pos: (i32, i32), color: i32) {
+        self.panels.insert(pos, color);
+    }
+
+    pub fn paint(&mut self, pos: (i32, i32), color: i32) {
+        self.set_color(pos, color);
+        self.painted.insert(pos);
+    }
+
+    /// Number of panels painted at least once, whatever their colour now
+    pub fn painted(&self) -> usize {
+        self.painted.len()
+    }
+
+    /// Number of panels currently painted `color`
+    pub fn count(&self, color: i32) -> usize {
+        self.panels.values().filter(|&&c| c == color).count()
+    }
+
+    /// The white panels as `#` on black `.`, top row first, bounded by the
+    /// white panels
+    pub fn render(&self) -> String {
+        let white = self
+            .panels
+            .iter()
+            .filter(|(_, &color)| color == WHITE)
+            .map(|(&pos, _)| pos)
+            .collect::<Vec<_>>();
+
+        let xs = white.iter().map(|&(x, _)| x);
+        let ys = white.iter().map(|&(_, y)| y);
+        let (min_x, max_x, min_y, max_y) =
+            match (xs.clone().min(), xs.max(), ys.clone().min(), ys.max()) {
+                (Some(min_x), Some(max_x), Some(min_y), Some(max_y)) => {
+                    (min_x, max_x, min_y, max_y)
+                }
+                _ => return String::new(),
+            };
+
+        let mut out = String::new();
+        for y in (min_y..=max_y).rev() {
+            for x in min_x..=max_x {
+                out.push(if self.color((x, y)) == WHITE {
+                    '#'
+                } else {
+                    '.'
+                });
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// Connects a program to a robot on a hull: each input is the colour of the
+/// panel under the robot, and each pair of outputs is a colour to paint it
+/// followed by a turn, after which the robot moves forward one panel.
+#[derive(Clone, Debug)]
+pub struct PaintingRobot {
+    hull: Hull,
+    pos: (i32, i32),
+    heading: Heading,
+    color: Option<i32>,
+    error: Option<String>,
+}
+
+impl PaintingRobot {
+    /// A robot facing up at (0, 0) on an unpainted hull
+    pub fn new() -> Self {
+        PaintingRobot::on(Hull::default())
+    }
+
+    pub fn on(hull: Hull) -> Self {
+        PaintingRobot {
+            hull,
+            pos: (0, 0),
+            heading: Heading::Up,
+            color: None,
+            error: None,
+        }
+    }
+
+    pub fn hull(&self) -> &Hull {
+        &self.hull
+    }
+
+    pub fn into_hull(self) -> Hull {
+        self.hull
+    }
+
+    pub fn position(&self) -> (i32, i32) {
+        self.pos
+    }
+
+    pub fn heading(&self) -> Heading {
+        self.heading
+    }
+
+    /// Fails with the first output the robot couldn't act on, if any
+    fn check(&mut self) -> CPUResult<()> {
+        match self.error.take() {
+            Some(message) => Err(CPUException::new(CPUExceptionKind::InvalidInput, message)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Default for PaintingRobot {
+    fn default() -> Self {
+        PaintingRobot::new()
+    }
+}
+
+impl IntcodeIO for PaintingRobot {
+    fn input(&mut self) -> CPUResult<Option<i32>> {
+        // A bad output can't fail the output instruction, so it fails the
+        // robot's next read instead, or the run if the program halts first
+        self.check()?;
+
+        Ok(Some(self.hull.color(self.pos)))
+    }
+
+    fn output(&mut self, value: i32) {
+        // Nothing after a bad output can be trusted
+        if self.error.is_some() {
+            return;
+        }
+
+        let color = match self.color.take() {
+            Some(color) => color,
+            None if value == BLACK || value == WHITE => {
+                self.color = Some(value);
+                return;
+            }
+            None => {
+                self.error = Some(format!("Invalid colour {} at {:?}", value, self.pos));
+                return;
+            }
+        };
+
+        self.heading = match value {
+            TURN_LEFT => self.heading.turn_left(),
+            TURN_RIGHT => self.heading.turn_right(),
+            _ => {
+                self.error = Some(format!("Invalid turn {} at {:?}", value, self.pos));
+                return;
+            }
+        };
+        self.hull.paint(self.pos, color);
+        self.pos = self.heading.step(self.pos);
+    }
+}
+
+/// Runs a CPU driving a robot until the program halts, failing if it stops
+/// for anything else or outputs something the robot can't act on
+pub fn run<O: Observer>(cpu: &mut IntcodeCPU<PaintingRobot, O>) -> CPUResult<()> {
+    match cpu.run()? {
+        CPUState::Halted => cpu.io_mut().check(),
+        state => Err(CPUException::new(
+            CPUExceptionKind::InvalidInput,
+            format!("Robot stopped in state {:?}", state),
+        )),
+    }
+}
+
+/// Runs a painting program to completion on a hull whose starting panel is
+/// `start`, returning the robot where it stopped
+pub fn paint(program: Vec<i32>, start: i32) -> CPUResult<PaintingRobot> {
+    let mut hull = Hull::default();
+    hull.set_color((0, 0), start);
+
+    let mut cpu = IntcodeCPU::with_io(program, PaintingRobot::on(hull));
+    run(&mut cpu)?;
+    Ok(cpu.io().clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads a colour before outputting each pair, ignoring it
+    fn scripted(pairs: &[(i32, i32)]) -> Vec<i32> {
+        let dst = pairs.len() as i32 * 6 + 1;
+        let mut program = pairs
+            .iter()
+            .flat_map(|&(color, turn)| vec![3, dst, 104, color, 104, turn])
+            .collect::<Vec<_>>();
+        program.extend(&[99, 0]);
+        program
+    }
+
+    #[test]
+    fn paints_example_hull() {
+        let program = scripted(&[(1, 0), (0, 0), (1, 0), (1, 0), (0, 1), (1, 0), (1, 0)]);
+        let robot = paint(program, BLACK).expect("Should have halted");
+
+        assert_eq!(robot.hull().painted(), 6);
+        assert_eq!(robot.hull().count(WHITE), 4);
+        assert_eq!(robot.position(), (0, 1));
+        assert_eq!(robot.heading(), Heading::Left);
+        assert_eq!(robot.hull().render(), "..#\n..#\n##.\n");
+    }
+
+    #[test]
+    fn robot_reports_panel_colour_and_bad_turns() {
+        // Repaints the first panel its own colour and turns right, then
+        // outputs the colour of the next panel
+        let program = vec![3, 11, 4, 11, 104, 1, 3, 11, 4, 11, 99, 0];
+        let robot = paint(program, WHITE).expect("Should have halted");
+        assert_eq!(robot.hull().color((0, 0)), WHITE);
+        assert_eq!(robot.hull().painted(), 1);
+        assert_eq!(robot.position(), (1, 0));
+        assert_eq!(robot.heading(), Heading::Right);
+
+        let program = scripted(&[(1, 2), (1, 0)]);
+        let ex = paint(program, BLACK).expect_err("Should have excepted at runtime");
+        assert_eq!(ex.kind(), CPUExceptionKind::InvalidInput);
+    }
+
+    #[test]
+    fn bad_outputs_fail_even_if_the_program_halts() {
+        for &pair in &[(1, 2), (2, 0)] {
+            let ex = paint(scripted(&[pair]), BLACK).expect_err("Should have failed");
+            assert_eq!(ex.kind(), CPUExceptionKind::InvalidInput);
+        }
+
+        let mut cpu = IntcodeCPU::with_io(scripted(&[(1, 0)]), PaintingRobot::new());
+        run(&mut cpu).expect("Should have halted");
+        assert_eq!(cpu.io().hull().painted(), 1);
+    }
+}
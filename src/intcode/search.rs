@@ -0,0 +1,366 @@
+use std::fmt;
+use std::ops::Range;
+use std::thread;
+
+use super::io::QueueIO;
+use super::{CPUException, CPUExceptionKind, CPUState, IntcodeCPU};
+
+/// The cartesian product of values to try for some memory positions and for
+/// a sequence of inputs.
+#[derive(Clone, Debug, Default)]
+pub struct SearchSpace {
+    positions: Vec<(usize, Range<i32>)>,
+    inputs: Vec<Range<i32>>,
+}
+
+impl SearchSpace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Try every value in `values` at memory position `pos`
+    pub fn position(mut self, pos: usize, values: Range<i32>) -> Self {
+        self.positions.push((pos, values));
+        self
+    }
+
+    /// Supply one more input, trying every value in `values`
+    pub fn input(mut self, values: Range<i32>) -> Self {
+        self.inputs.push(values);
+        self
+    }
+
+    fn dimensions(&self) -> impl Iterator<Item = &Range<i32>> {
+        self.positions
+            .iter()
+            .map(|(_, values)| values)
+            .chain(self.inputs.iter())
+    }
+
+    /// Number of assignments in the space
+    pub fn len(&self) -> usize {
+        self.dimensions()
+            .map(|values| values.end.saturating_sub(values.start).max(0) as usize)
+            .product()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The `index`th assignment, with the last dimension varying fastest
+    fn assignment(&self, mut index: usize) -> Assignment {
+        let mut values = self
+            .dimensions()
+            .collect::<Vec<_>>()
+            .into_iter()
+            .rev()
+            .map(|range| {
+                let size = (range.end - range.start) as usize;
+                let val = range.start + (index % size) as i32;
+                index /= size;
+                val
+            })
+            .collect::<Vec<i32>>();
+        values.reverse();
+
+        let inputs = values.split_off(self.positions.len());
+
+        Assignment {
+            positions: self
+                .positions
+                .iter()
+                .map(|(pos, _)| *pos)
+                .zip(values)
+                .collect(),
+            inputs,
+        }
+    }
+}
+
+/// One point in a `SearchSpace`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Assignment {
+    pub positions: Vec<(usize, i32)>,
+    pub inputs: Vec<i32>,
+}
+
+impl Assignment {
+    /// Value assigned to memory position `pos`, if it is part of the search
+    pub fn position(&self, pos: usize) -> Option<i32> {
+        self.positions
+            .iter()
+            .find(|(p, _)| *p == pos)
+            .map(|(_, val)| *val)
+    }
+}
+
+impl fmt::Display for Assignment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut parts = self
+            .positions
+            .iter()
+            .map(|(pos, val)| format!("[{}] = {}", pos, val))
+            .collect::<Vec<_>>();
+        if !self.inputs.is_empty() {
+            let inputs = self
+                .inputs
+                .iter()
+                .map(i32::to_string)
+                .collect::<Vec<_>>()
+                .join(",");
+            parts.push(format!("inputs = {}", inputs));
+        }
+
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+/// An assignment for which the program raised an exception
+#[derive(Clone, Debug)]
+pub struct SearchFailure {
+    pub assignment: Assignment,
+    pub exception: CPUException,
+    pub pc: usize,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SearchResults {
+    /// Assignments satisfying the predicate, in search space order
+    pub matches: Vec<Assignment>,
+    pub failures: Vec<SearchFailure>,
+}
+
+/// Runs a program once for every assignment in a search space and collects
+/// those for which a predicate over the finished CPU holds.
+pub struct Search<'a> {
+    program: &'a [i32],
+    space: SearchSpace,
+    threads: usize,
+    step_limit: Option<u64>,
+}
+
+enum Outcome {
+    Match(Assignment),
+    Failure(SearchFailure),
+}
+
+impl<'a> Search<'a> {
+    pub fn new(program: &'a [i32], space: SearchSpace) -> Self {
+        Search {
+            program,
+            space,
+            threads: 1,
+            step_limit: None,
+        }
+    }
+
+    /// Split the search space across this many worker threads
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Give up on any single run after this many steps
+    pub fn step_limit(mut self, limit: u64) -> Self {
+        self.step_limit = Some(limit);
+        self
+    }
+
+    /// Runs a single assignment on a fork of `template`
+    fn run_one<P>(
+        &self,
+        assignment: Assignment,
+        template: &IntcodeCPU<QueueIO>,
+        predicate: &P,
+    ) -> Option<Outcome>
+    where
+        P: Fn(&IntcodeCPU<QueueIO>) -> bool,
+    {
+        let mut cpu = template.fork();
+        for &(pos, val) in &assignment.positions {
+            // Running the unmodified program instead would report bogus
+            // matches
+            if cpu.set_position(pos, val).is_none() {
+                return Some(Outcome::Failure(SearchFailure {
+                    assignment,
+                    exception: CPUException::out_of_bounds("SEARCH!position", pos),
+                    pc: cpu.pc(),
+                }));
+            }
+        }
+        for &input in &assignment.inputs {
+            cpu.io_mut().push_input(input);
+        }
+
+        match cpu.run() {
+            Ok(CPUState::Halted) if predicate(&cpu) => Some(Outcome::Match(assignment)),
+            Ok(CPUState::Halted) => None,
+            Ok(_) => Some(Outcome::Failure(SearchFailure {
+                assignment,
+                exception: CPUException::new(
+                    CPUExceptionKind::InvalidInput,
+                    "Program needed more input than was supplied".into(),
+                ),
+                pc: cpu.pc(),
+            })),
+            Err(exception) => Some(Outcome::Failure(SearchFailure {
+                assignment,
+                exception,
+                pc: cpu.pc(),
+            })),
+        }
+    }
+
+    /// Outcomes for every `step`th assignment starting at `first`
+    fn run_slice<P>(&self, first: usize, step: usize, predicate: &P) -> Vec<(usize, Outcome)>
+    where
+        P: Fn(&IntcodeCPU<QueueIO>) -> bool,
+    {
+        // Each worker builds its own template, since CPUs can't be sent
+        // between threads; forks of it share the unmodified pages
+        let mut template = IntcodeCPU::with_io(self.program.to_vec(), QueueIO::default());
+        template.set_step_limit(self.step_limit);
+
+        (first..self.space.len())
+            .step_by(step)
+            .filter_map(|index| {
+                self.run_one(self.space.assignment(index), &template, predicate)
+                    .map(|outcome| (index, outcome))
+            })
+            .collect()
+    }
+
+    pub fn run<P>(&self, predicate: P) -> SearchResults
+    where
+        P: Fn(&IntcodeCPU<QueueIO>) -> bool + Sync,
+    {
+        let mut outcomes = if self.threads == 1 {
+            self.run_slice(0, 1, &predicate)
+        } else {
+            let predicate = &predicate;
+            thread::scope(|scope| {
+                let workers = (0..self.threads)
+                    .map(|first| {
+                        scope.spawn(move || self.run_slice(first, self.threads, predicate))
+                    })
+                    .collect::<Vec<_>>();
+
+                workers
+                    .into_iter()
+                    .flat_map(|worker| worker.join().expect("Search worker panicked"))
+                    .collect::<Vec<_>>()
+            })
+        };
+        outcomes.sort_by_key(|(index, _)| *index);
+
+        let mut results = SearchResults::default();
+        for (_, outcome) in outcomes {
+            match outcome {
+                Outcome::Match(assignment) => results.matches.push(assignment),
+                Outcome::Failure(failure) => results.failures.push(failure),
+            }
+        }
+
+        results
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn enumerates_assignments_in_order() {
+        let space = SearchSpace::new().position(1, 0..2).input(5..8);
+
+        assert_eq!(space.len(), 6);
+        assert_eq!(
+            space.assignment(4),
+            Assignment {
+                positions: vec![(1, 1)],
+                inputs: vec![6],
+            }
+        );
+    }
+
+    #[test]
+    fn finds_all_matching_positions() {
+        let program = [1, 5, 6, 0, 99, 0, 0];
+        let space = SearchSpace::new().position(5, 0..10).position(6, 0..10);
+
+        let serial = Search::new(&program, space.clone()).run(|cpu| cpu.output() == 12);
+        let parallel = Search::new(&program, space)
+            .threads(3)
+            .run(|cpu| cpu.output() == 12);
+
+        let pairs = serial
+            .matches
+            .iter()
+            .map(|a| (a.position(5).unwrap(), a.position(6).unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            pairs,
+            vec![(3, 9), (4, 8), (5, 7), (6, 6), (7, 5), (8, 4), (9, 3)]
+        );
+        assert_eq!(serial.matches, parallel.matches);
+    }
+
+    #[test]
+    fn searches_input_sequences() {
+        let program = [3, 11, 1002, 11, 2, 12, 4, 12, 99, 0, 0, 0, 0];
+        let space = SearchSpace::new().input(0..10);
+
+        let results = Search::new(&program, space).run(|cpu| cpu.io().outputs() == [8]);
+
+        assert_eq!(results.matches.len(), 1);
+        assert_eq!(results.matches[0].inputs, vec![4]);
+    }
+
+    #[test]
+    fn reports_failures() {
+        let program = [1, 0, 0, 0, 99];
+        let space = SearchSpace::new().position(1, 0..7).position(2, 0..7);
+
+        let results = Search::new(&program, space).threads(2).run(|_| true);
+
+        assert_eq!(results.matches.len(), 25);
+        assert_eq!(results.failures.len(), 24);
+        assert_eq!(
+            results.failures[0].assignment.to_string(),
+            "[1] = 0, [2] = 5"
+        );
+    }
+
+    #[test]
+    fn overflow_is_a_failure() {
+        // Multiplies two immediates, the second of which overflows
+        let program = [1102, 0, 65_536, 0, 99];
+        let space = SearchSpace::new().position(1, 32_767..32_769);
+
+        let results = Search::new(&program, space).threads(2).run(|_| true);
+
+        assert_eq!(results.matches.len(), 1);
+        assert_eq!(results.failures.len(), 1);
+        assert_eq!(
+            results.failures[0].exception.kind(),
+            CPUExceptionKind::Overflow
+        );
+    }
+
+    #[test]
+    fn positions_outside_program_are_failures() {
+        let program = [1, 0, 0, 0, 99];
+        let space = SearchSpace::new().position(1, 0..2).position(9, 0..2);
+
+        let results = Search::new(&program, space).run(|_| true);
+
+        assert!(results.matches.is_empty());
+        assert_eq!(results.failures.len(), 4);
+        assert!(results
+            .failures
+            .iter()
+            .all(|f| f.exception.kind() == CPUExceptionKind::OutOfBounds
+                && f.exception.address() == Some(9)));
+    }
+}
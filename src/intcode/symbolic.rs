@@ -0,0 +1,517 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Range;
+use std::rc::Rc;
+
+use super::{CPUException, CPUOp, Operand};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Expr {
+    Const(i64),
+    /// The initial value of the given cell
+    Var(usize),
+    Add(Rc<Expr>, Rc<Expr>),
+    Mul(Rc<Expr>, Rc<Expr>),
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Const(val) => write!(f, "{}", val),
+            Expr::Var(pos) => write!(f, "[{}]", pos),
+            Expr::Add(a, b) => write!(f, "({} + {})", a, b),
+            Expr::Mul(a, b) => write!(f, "({} * {})", a, b),
+        }
+    }
+}
+
+/// Sum of monomials, each a sorted list of variables mapped to its coefficient
+type Polynomial = BTreeMap<Vec<usize>, i64>;
+
+impl Expr {
+    fn polynomial(&self) -> Option<Polynomial> {
+        let mut out = Polynomial::new();
+
+        match self {
+            Expr::Const(val) => {
+                out.insert(vec![], *val);
+            }
+            Expr::Var(pos) => {
+                out.insert(vec![*pos], 1);
+            }
+            Expr::Add(a, b) => {
+                out = a.polynomial()?;
+                for (monomial, coeff) in b.polynomial()? {
+                    let entry = out.entry(monomial).or_insert(0);
+                    *entry = entry.checked_add(coeff)?;
+                }
+            }
+            Expr::Mul(a, b) => {
+                let b = b.polynomial()?;
+                for (ma, ca) in a.polynomial()? {
+                    for (mb, cb) in &b {
+                        let mut monomial = ma.iter().chain(mb.iter()).cloned().collect::<Vec<_>>();
+                        monomial.sort();
+                        let entry = out.entry(monomial).or_insert(0);
+                        *entry = entry.checked_add(ca.checked_mul(*cb)?)?;
+                    }
+                }
+            }
+        }
+
+        out.retain(|_, coeff| *coeff != 0);
+        Some(out)
+    }
+
+    /// Simplifies to `constant + sum(coeff * var)`, if the expression has no
+    /// products of variables
+    pub fn linear(&self) -> Option<Linear> {
+        let mut linear = Linear {
+            constant: 0,
+            coeffs: BTreeMap::new(),
+        };
+
+        for (monomial, coeff) in self.polynomial()? {
+            match monomial[..] {
+                [] => linear.constant = coeff,
+                [var] => {
+                    linear.coeffs.insert(var, coeff);
+                }
+                _ => return None,
+            }
+        }
+
+        Some(linear)
+    }
+
+    /// Evaluates with every variable looked up in `vars`
+    pub fn eval(&self, vars: &BTreeMap<usize, i64>) -> Option<i64> {
+        match self {
+            Expr::Const(val) => Some(*val),
+            Expr::Var(pos) => vars.get(pos).copied(),
+            Expr::Add(a, b) => a.eval(vars)?.checked_add(b.eval(vars)?),
+            Expr::Mul(a, b) => a.eval(vars)?.checked_mul(b.eval(vars)?),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Linear {
+    pub constant: i64,
+    pub coeffs: BTreeMap<usize, i64>,
+}
+
+impl fmt::Display for Linear {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.constant)?;
+        for (var, coeff) in &self.coeffs {
+            write!(f, " + {} * [{}]", coeff, var)?;
+        }
+        Ok(())
+    }
+}
+
+impl Linear {
+    /// Every assignment of the variables within `ranges` for which the
+    /// expression equals `target`. All but the last variable are enumerated
+    /// and the last is solved for directly.
+    pub fn solve(&self, target: i64, ranges: &[(usize, Range<i64>)]) -> Vec<BTreeMap<usize, i64>> {
+        let mut solutions = vec![];
+        self.solve_from(
+            target - self.constant,
+            ranges,
+            &mut BTreeMap::new(),
+            &mut solutions,
+        );
+        solutions
+    }
+
+    fn solve_from(
+        &self,
+        remaining: i64,
+        ranges: &[(usize, Range<i64>)],
+        assignment: &mut BTreeMap<usize, i64>,
+        solutions: &mut Vec<BTreeMap<usize, i64>>,
+    ) {
+        let coeff = |var: usize| self.coeffs.get(&var).copied().unwrap_or(0);
+
+        match ranges {
+            [] => {
+                if remaining == 0 {
+                    solutions.push(assignment.clone());
+                }
+            }
+            [(var, range)] => {
+                let a = coeff(*var);
+                let values: Vec<i64> = if a == 0 {
+                    if remaining == 0 {
+                        range.clone().collect()
+                    } else {
+                        vec![]
+                    }
+                } else if remaining % a == 0 && range.contains(&(remaining / a)) {
+                    vec![remaining / a]
+                } else {
+                    vec![]
+                };
+
+                for val in values {
+                    assignment.insert(*var, val);
+                    solutions.push(assignment.clone());
+                }
+                assignment.remove(var);
+            }
+            [(var, range), rest @ ..] => {
+                let a = coeff(*var);
+                for val in range.clone() {
+                    assignment.insert(*var, val);
+                    self.solve_from(remaining - a * val, rest, assignment, solutions);
+                }
+                assignment.remove(var);
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SymValue {
+    Concrete(i32),
+    Symbolic(Rc<Expr>),
+    /// Derived from the symbols in a way that can't be expressed, such as a
+    /// comparison or a read through a symbolic address
+    Unknown,
+}
+
+impl SymValue {
+    fn expr(&self) -> Option<Rc<Expr>> {
+        match self {
+            SymValue::Concrete(val) => Some(Rc::new(Expr::Const(i64::from(*val)))),
+            SymValue::Symbolic(expr) => Some(expr.clone()),
+            SymValue::Unknown => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum SymbolicError {
+    /// The program raised an exception that doesn't depend on the symbols
+    Exception(CPUException),
+    /// An opcode, write address or jump depends on the symbols
+    SymbolicControl {
+        pc: usize,
+    },
+    /// Input isn't supported in symbolic mode
+    Input {
+        pc: usize,
+    },
+    Overflow {
+        pc: usize,
+    },
+    StepLimitExceeded(u64),
+    /// The cell being solved for has no expression
+    Unknown(usize),
+    NonLinear(Rc<Expr>),
+}
+
+impl fmt::Display for SymbolicError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SymbolicError::Exception(ex) => write!(f, "CPU exception {}", ex),
+            SymbolicError::SymbolicControl { pc } => {
+                write!(f, "control flow at pc {} depends on symbols", pc)
+            }
+            SymbolicError::Input { pc } => write!(f, "input at pc {} is not supported", pc),
+            SymbolicError::Overflow { pc } => write!(f, "arithmetic overflow at pc {}", pc),
+            SymbolicError::StepLimitExceeded(limit) => {
+                write!(f, "program did not halt within {} steps", limit)
+            }
+            SymbolicError::Unknown(pos) => write!(f, "cell {} has no symbolic expression", pos),
+            SymbolicError::NonLinear(expr) => write!(f, "{} is not linear", expr),
+        }
+    }
+}
+
+impl From<CPUException> for SymbolicError {
+    fn from(ex: CPUException) -> Self {
+        SymbolicError::Exception(ex)
+    }
+}
+
+/// Executes a program with some cells holding symbolic variables instead of
+/// values. Add and Mul over symbols build expressions; anything that makes
+/// the path through the program depend on the symbols stops execution.
+pub struct SymbolicCPU {
+    memory: Vec<SymValue>,
+    pc: usize,
+    address_checks: Vec<Rc<Expr>>,
+}
+
+impl SymbolicCPU {
+    pub fn new(program: &[i32], symbols: &[usize]) -> Self {
+        let mut memory = program
+            .iter()
+            .map(|&val| SymValue::Concrete(val))
+            .collect::<Vec<_>>();
+        for &pos in symbols {
+            if let Some(cell) = memory.get_mut(pos) {
+                *cell = SymValue::Symbolic(Rc::new(Expr::Var(pos)));
+            }
+        }
+
+        SymbolicCPU {
+            memory,
+            pc: 0,
+            address_checks: vec![],
+        }
+    }
+
+    fn cell(&self, pos: usize, ident: &str) -> Result<&SymValue, SymbolicError> {
+        self.memory
+            .get(pos)
+            .ok_or_else(|| CPUException::out_of_bounds(ident, pos).into())
+    }
+
+    fn operand(
+        &mut self,
+        oper: Operand,
+        param: usize,
+        ident: &str,
+    ) -> Result<SymValue, SymbolicError> {
+        match oper {
+            Operand::Immediate(_) => Ok(self.cell(param, ident)?.clone()),
+            Operand::Position(_) => match self.cell(param, ident)?.clone() {
+                SymValue::Concrete(pos) => Ok(self.cell(pos as usize, ident)?.clone()),
+                SymValue::Symbolic(addr) => {
+                    // Whatever is read, the address itself must be in bounds
+                    self.address_checks.push(addr);
+                    Ok(SymValue::Unknown)
+                }
+                SymValue::Unknown => Ok(SymValue::Unknown),
+            },
+        }
+    }
+
+    fn concrete(&self, val: &SymValue) -> Result<i32, SymbolicError> {
+        match val {
+            SymValue::Concrete(val) => Ok(*val),
+            _ => Err(SymbolicError::SymbolicControl { pc: self.pc }),
+        }
+    }
+
+    fn store(&mut self, param: usize, val: SymValue, ident: &str) -> Result<(), SymbolicError> {
+        let dst = self.concrete(self.cell(param, ident)?)? as usize;
+        let cell = self
+            .memory
+            .get_mut(dst)
+            .ok_or_else(|| CPUException::out_of_bounds(ident, dst))?;
+        *cell = val;
+        Ok(())
+    }
+
+    fn arith(
+        &self,
+        a: SymValue,
+        b: SymValue,
+        concrete: fn(i32, i32) -> Option<i32>,
+        symbolic: fn(Rc<Expr>, Rc<Expr>) -> Expr,
+    ) -> Result<SymValue, SymbolicError> {
+        match (a, b) {
+            (SymValue::Concrete(a), SymValue::Concrete(b)) => concrete(a, b)
+                .map(SymValue::Concrete)
+                .ok_or(SymbolicError::Overflow { pc: self.pc }),
+            (a, b) => match (a.expr(), b.expr()) {
+                (Some(a), Some(b)) => Ok(SymValue::Symbolic(Rc::new(symbolic(a, b)))),
+                _ => Ok(SymValue::Unknown),
+            },
+        }
+    }
+
+    fn compare(a: SymValue, b: SymValue, cmp: fn(&i32, &i32) -> bool) -> SymValue {
+        match (a, b) {
+            (SymValue::Concrete(a), SymValue::Concrete(b)) => {
+                SymValue::Concrete(i32::from(cmp(&a, &b)))
+            }
+            _ => SymValue::Unknown,
+        }
+    }
+
+    /// Executes one instruction, returning false once the program halts
+    fn step(&mut self) -> Result<bool, SymbolicError> {
+        let pc = self.pc;
+        let opcode = self.concrete(self.cell(pc, "FETCH!OP")?)?;
+        let memory = &self.memory;
+        // Symbolic parameters decode as 0; their real contents are looked
+        // up from the parameter cell when the operand is used
+        let op = CPUOp::decode(
+            pc,
+            |addr| match memory.get(addr) {
+                Some(SymValue::Concrete(val)) => Some(*val),
+                Some(_) if addr == pc => Some(opcode),
+                Some(_) => Some(0),
+                None => None,
+            },
+            false,
+        )?;
+
+        let offset = op.next_pc_offset();
+        match op {
+            CPUOp::Add { src1, src2, .. } => {
+                let a = self.operand(src1, pc + 1, "EXEC!ADD.src1")?;
+                let b = self.operand(src2, pc + 2, "EXEC!ADD.src2")?;
+                let val = self.arith(a, b, i32::checked_add, Expr::Add)?;
+                self.store(pc + 3, val, "EXEC!ADD.dst")?;
+            }
+            CPUOp::Mul { src1, src2, .. } => {
+                let a = self.operand(src1, pc + 1, "EXEC!MUL.src1")?;
+                let b = self.operand(src2, pc + 2, "EXEC!MUL.src2")?;
+                let val = self.arith(a, b, i32::checked_mul, Expr::Mul)?;
+                self.store(pc + 3, val, "EXEC!MUL.dst")?;
+            }
+            CPUOp::CompareLess { cmp1, cmp2, .. } => {
+                let a = self.operand(cmp1, pc + 1, "EXEC!LT.cmp1")?;
+                let b = self.operand(cmp2, pc + 2, "EXEC!LT.cmp2")?;
+                self.store(pc + 3, SymbolicCPU::compare(a, b, i32::lt), "EXEC!LT.dst")?;
+            }
+            CPUOp::CompareEqual { cmp1, cmp2, .. } => {
+                let a = self.operand(cmp1, pc + 1, "EXEC!EQ.cmp1")?;
+                let b = self.operand(cmp2, pc + 2, "EXEC!EQ.cmp2")?;
+                self.store(pc + 3, SymbolicCPU::compare(a, b, i32::eq), "EXEC!EQ.dst")?;
+            }
+            CPUOp::JumpZero { cmp, to } | CPUOp::JumpNonZero { cmp, to } => {
+                let jump_if_zero = matches!(op, CPUOp::JumpZero { .. });
+                let cmp = self.operand(cmp, pc + 1, "EXEC!JMP.cmp")?;
+                let to = self.operand(to, pc + 2, "EXEC!JMP.to")?;
+
+                if (self.concrete(&cmp)? == 0) == jump_if_zero {
+                    self.pc = self.concrete(&to)? as usize;
+                    return Ok(true);
+                }
+            }
+            CPUOp::Output(src) => {
+                self.operand(src, pc + 1, "EXEC!OUTPUT.src")?;
+            }
+            CPUOp::Input(_) => return Err(SymbolicError::Input { pc }),
+            CPUOp::Halt => return Ok(false),
+            CPUOp::Undefined(opcode) => return Err(CPUException::invalid_opcode(opcode).into()),
+        }
+
+        self.pc += offset;
+        Ok(true)
+    }
+
+    pub fn run(&mut self, max_steps: u64) -> Result<(), SymbolicError> {
+        for _ in 0..max_steps {
+            if !self.step()? {
+                return Ok(());
+            }
+        }
+
+        Err(SymbolicError::StepLimitExceeded(max_steps))
+    }
+
+    pub fn get_position(&self, pos: usize) -> Option<&SymValue> {
+        self.memory.get(pos)
+    }
+
+    /// After a run, finds every assignment of the symbols within `ranges`
+    /// that leaves `target` in cell `pos`, excluding any that would have
+    /// read through an out of bounds address.
+    pub fn solve(
+        &self,
+        pos: usize,
+        target: i64,
+        ranges: &[(usize, Range<i64>)],
+    ) -> Result<Vec<BTreeMap<usize, i64>>, SymbolicError> {
+        let expr = match self.memory.get(pos).and_then(SymValue::expr) {
+            Some(expr) => expr,
+            None => return Err(SymbolicError::Unknown(pos)),
+        };
+        let linear = expr
+            .linear()
+            .ok_or_else(|| SymbolicError::NonLinear(expr.clone()))?;
+        let len = self.memory.len() as i64;
+
+        Ok(linear
+            .solve(target, ranges)
+            .into_iter()
+            .filter(|vars| {
+                self.address_checks
+                    .iter()
+                    .all(|addr| addr.eval(vars).is_some_and(|addr| addr >= 0 && addr < len))
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_linear_expression() {
+        // [0] = ([14] + [15]) * 3 + 4
+        let program = [1, 14, 15, 13, 1002, 13, 3, 13, 1001, 13, 4, 0, 99, 0, 0, 0];
+        let mut cpu = SymbolicCPU::new(&program, &[14, 15]);
+        cpu.run(100).expect("Should not have excepted at runtime");
+
+        let linear = match cpu.get_position(0) {
+            Some(SymValue::Symbolic(expr)) => expr.linear().unwrap(),
+            other => panic!("Expected an expression, got {:?}", other),
+        };
+
+        assert_eq!(linear.to_string(), "4 + 3 * [14] + 3 * [15]");
+    }
+
+    #[test]
+    fn symbolic_addresses_constrain_solutions() {
+        // Reads through [1] and [2] as addresses, then [0] = 5 * ([1] + [2])
+        let program = [1, 0, 0, 3, 1, 1, 2, 3, 2, 3, 13, 0, 99, 5];
+        let mut cpu = SymbolicCPU::new(&program, &[1, 2]);
+        cpu.run(100).expect("Should not have excepted at runtime");
+
+        let solutions = cpu.solve(0, 125, &[(1, 0..20), (2, 0..20)]).unwrap();
+        let pairs = solutions
+            .iter()
+            .map(|vars| (vars[&1], vars[&2]))
+            .collect::<Vec<_>>();
+
+        assert_eq!(pairs, vec![(12, 13), (13, 12)]);
+    }
+
+    #[test]
+    fn solves_for_target() {
+        let linear = Linear {
+            constant: 7,
+            coeffs: vec![(1, 100), (2, 1)].into_iter().collect(),
+        };
+
+        let solutions = linear.solve(2007, &[(1, 0..100), (2, 0..100)]);
+
+        assert_eq!(solutions.len(), 1);
+        assert_eq!(solutions[0][&1], 20);
+        assert_eq!(solutions[0][&2], 0);
+    }
+
+    #[test]
+    fn products_of_symbols_are_not_linear() {
+        let program = [2, 5, 6, 0, 99, 0, 0];
+        let mut cpu = SymbolicCPU::new(&program, &[5, 6]);
+        cpu.run(100).expect("Should not have excepted at runtime");
+
+        match cpu.solve(0, 42, &[(5, 0..5), (6, 0..5)]) {
+            Err(SymbolicError::NonLinear(_)) => {}
+            other => panic!("Expected a non-linear error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn symbolic_branch_stops_execution() {
+        let program = [1005, 1, 0, 99];
+        let mut cpu = SymbolicCPU::new(&program, &[1]);
+
+        match cpu.run(100) {
+            Err(SymbolicError::SymbolicControl { pc: 0 }) => {}
+            other => panic!("Expected symbolic control flow, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::BTreeSet;
+use std::fmt;
+
+use super::Operand;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TaintSource {
+    /// The nth value read by an `Input` instruction, counting from 0
+    Input(usize),
+    /// The value a cell held in the initial image
+    Cell(usize),
+}
+
+impl fmt::Display for TaintSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TaintSource::Input(n) => write!(f, "input#{}", n),
+            TaintSource::Cell(pos) => write!(f, "[{}]", pos),
+        }
+    }
+}
+
+pub type TaintSet = BTreeSet<TaintSource>;
+
+/// Shadow memory recording which sources each cell's value was derived from.
+///
+/// Taint flows from operands (including the cell an address was read from)
+/// into the destination of Add, Mul and the comparisons, and from operands
+/// into outputs. Control dependencies through jumps are not tracked.
+#[derive(Clone, Debug)]
+pub struct TaintTracker {
+    cells: Vec<TaintSet>,
+    outputs: Vec<TaintSet>,
+    inputs_read: usize,
+}
+
+impl TaintTracker {
+    /// Tracks memory of `len` cells, with each of `seeds` tainted by its
+    /// own initial value.
+    pub fn new(len: usize, seeds: &[usize]) -> Self {
+        let mut cells = vec![TaintSet::new(); len];
+        for &pos in seeds {
+            if let Some(cell) = cells.get_mut(pos) {
+                cell.insert(TaintSource::Cell(pos));
+            }
+        }
+
+        TaintTracker {
+            cells,
+            outputs: vec![],
+            inputs_read: 0,
+        }
+    }
+
+    fn cell_taint(&self, pos: usize) -> TaintSet {
+        self.cells.get(pos).cloned().unwrap_or_default()
+    }
+
+    /// Taint of an operand fetched from cell `param`
+    fn operand_taint(&self, oper: Operand, param: usize) -> TaintSet {
+        let mut taint = self.cell_taint(param);
+        if let Operand::Position(pos) = oper {
+            taint.extend(self.cell_taint(pos));
+        }
+        taint
+    }
+
+    fn set_cell(&mut self, pos: usize, taint: TaintSet) {
+        if let Some(cell) = self.cells.get_mut(pos) {
+            *cell = taint;
+        }
+    }
+
+    /// Record `dst` (whose address is in cell `dst_param`) being computed
+    /// from `srcs`, each given with the cell it was fetched from
+    pub(super) fn propagate(&mut self, srcs: &[(Operand, usize)], dst: usize, dst_param: usize) {
+        let mut taint = self.cell_taint(dst_param);
+        for &(oper, param) in srcs {
+            taint.extend(self.operand_taint(oper, param));
+        }
+        self.set_cell(dst, taint);
+    }
+
+    pub(super) fn record_input(&mut self, dst: usize, dst_param: usize) {
+        let mut taint = self.cell_taint(dst_param);
+        taint.insert(TaintSource::Input(self.inputs_read));
+        self.inputs_read += 1;
+        self.set_cell(dst, taint);
+    }
+
+    pub(super) fn record_output(&mut self, src: Operand, param: usize) {
+        let taint = self.operand_taint(src, param);
+        self.outputs.push(taint);
+    }
+
+    pub fn cell(&self, pos: usize) -> Option<&TaintSet> {
+        self.cells.get(pos)
+    }
+
+    /// Taint of each value output so far, in order
+    pub fn outputs(&self) -> &[TaintSet] {
+        &self.outputs
+    }
+
+    /// Every cell with a non-empty taint set
+    pub fn tainted_cells(&self) -> impl Iterator<Item = (usize, &TaintSet)> {
+        self.cells
+            .iter()
+            .enumerate()
+            .filter(|(_, taint)| !taint.is_empty())
+    }
+}
+
+/// Formats a taint set as a comma separated list of sources
+pub fn describe(taint: &TaintSet) -> String {
+    if taint.is_empty() {
+        return "(nothing)".to_string();
+    }
+
+    taint
+        .iter()
+        .map(TaintSource::to_string)
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::io::QueueIO;
+    use crate::intcode::IntcodeCPU;
+
+    #[test]
+    fn input_flows_to_output() {
+        let program = vec![3, 9, 1002, 9, 2, 10, 4, 10, 99, 0, 0];
+        let mut cpu = IntcodeCPU::with_io(program, QueueIO::new(vec![21]));
+        cpu.enable_taint_tracking(&[]);
+        cpu.run().expect("Should not have excepted at runtime");
+
+        let taint = cpu.taint().unwrap();
+        let expected = [TaintSource::Input(0)]
+            .iter()
+            .cloned()
+            .collect::<TaintSet>();
+
+        assert_eq!(cpu.io().outputs(), &[42]);
+        assert_eq!(taint.cell(10), Some(&expected));
+        assert_eq!(taint.outputs(), &[expected]);
+    }
+
+    #[test]
+    fn seeded_cells_flow_through_addresses_and_values() {
+        let program = vec![1, 1, 1, 4, 99, 5, 6, 0, 99];
+        let mut cpu = IntcodeCPU::with_io(program, QueueIO::default());
+        cpu.enable_taint_tracking(&[1, 2]);
+        cpu.run().expect("Should not have excepted at runtime");
+
+        let taint = cpu.taint().unwrap();
+
+        assert_eq!(describe(taint.cell(4).unwrap()), "[1], [2]");
+        assert_eq!(describe(taint.cell(0).unwrap()), "(nothing)");
+        assert_eq!(
+            taint
+                .tainted_cells()
+                .map(|(pos, _)| pos)
+                .collect::<Vec<usize>>(),
+            vec![1, 2, 4]
+        );
+    }
+}
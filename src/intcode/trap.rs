@@ -0,0 +1,168 @@
+use super::memory::Memory;
+use super::CPUException;
+
+/// What the CPU does once a trap handler returns
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TrapAction {
+    /// Stop, returning the exception from `step()` as if there were no
+    /// handler
+    Abort,
+    /// Execute the instruction at the (possibly patched) pc again
+    Retry,
+    /// Carry on from the instruction after the one that faulted. The
+    /// faulting instruction has no effect beyond what the handler did.
+    Resume,
+}
+
+/// Handles one kind of exception raised by `IntcodeCPU::step`. Clones of a
+/// CPU share its handlers.
+pub type TrapHandler = Box<dyn FnMut(&mut Trap) -> TrapAction>;
+
+/// The state a trap handler can inspect and patch
+pub struct Trap<'a> {
+    exception: &'a CPUException,
+    pc: &'a mut usize,
+    memory: &'a mut Memory,
+}
+
+impl<'a> Trap<'a> {
+    pub(super) fn new(
+        exception: &'a CPUException,
+        pc: &'a mut usize,
+        memory: &'a mut Memory,
+    ) -> Self {
+        Trap {
+            exception,
+            pc,
+            memory,
+        }
+    }
+
+    pub fn exception(&self) -> &CPUException {
+        self.exception
+    }
+
+    /// Address of the instruction that faulted, until `set_pc` moves it
+    pub fn pc(&self) -> usize {
+        *self.pc
+    }
+
+    /// Moves the pc. `Retry` executes from here, while `Resume` skips the
+    /// instruction found here.
+    pub fn set_pc(&mut self, pc: usize) {
+        *self.pc = pc;
+    }
+
+    pub fn memory(&self) -> &Memory {
+        self.memory
+    }
+
+    /// Memory may be resized, e.g. growing it so that a read out of bounds
+    /// finds a default value on `Retry`
+    pub fn memory_mut(&mut self) -> &mut Memory {
+        self.memory
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::io::QueueIO;
+    use crate::intcode::{CPUExceptionKind, CPUState, IntcodeCPU};
+
+    #[test]
+    fn invalid_opcode_can_be_patched_or_skipped() {
+        let mut cpu = IntcodeCPU::with_io(vec![104, 1, 42, 104, 2, 77], QueueIO::default());
+        cpu.set_trap_handler(
+            CPUExceptionKind::InvalidOpcode,
+            Box::new(|trap| {
+                if trap.pc() == 2 {
+                    TrapAction::Resume
+                } else {
+                    let pc = trap.pc();
+                    trap.memory_mut().set(pc, 99);
+                    TrapAction::Retry
+                }
+            }),
+        );
+
+        assert_eq!(cpu.run().unwrap(), CPUState::Halted);
+        assert_eq!(cpu.io().outputs(), &[1, 2]);
+        assert_eq!(cpu.inspect_state(), &[104, 1, 42, 104, 2, 99]);
+    }
+
+    #[test]
+    fn out_of_bounds_read_can_be_given_a_default() {
+        let program = vec![1, 0, 10, 0, 4, 0, 99];
+        let mut cpu = IntcodeCPU::with_io(program.clone(), QueueIO::default());
+        cpu.set_trap_handler(
+            CPUExceptionKind::OutOfBounds,
+            Box::new(|trap| match trap.exception().address() {
+                Some(pos) => {
+                    trap.memory_mut().resize(pos + 1, 0);
+                    TrapAction::Retry
+                }
+                None => TrapAction::Abort,
+            }),
+        );
+
+        assert_eq!(cpu.run().unwrap(), CPUState::Halted);
+        assert_eq!(cpu.io().outputs(), &[1]);
+        assert_eq!(cpu.inspect_state().len(), 11);
+
+        let mut cpu = IntcodeCPU::with_io(program, QueueIO::default());
+        cpu.set_trap_handler(
+            CPUExceptionKind::OutOfBounds,
+            Box::new(|_| TrapAction::Abort),
+        );
+        let ex = cpu.run().expect_err("Should have excepted at runtime");
+        assert_eq!(ex.kind(), CPUExceptionKind::OutOfBounds);
+        assert_eq!(ex.address(), Some(10));
+        assert_eq!(cpu.pc(), 0);
+    }
+
+    #[test]
+    fn resuming_past_the_end_of_the_address_space_fails() {
+        // Jumps to -1, which wraps to usize::MAX
+        let mut cpu = IntcodeCPU::with_io(vec![1105, 1, -1], QueueIO::default());
+        cpu.set_trap_handler(
+            CPUExceptionKind::OutOfBounds,
+            Box::new(|_| TrapAction::Resume),
+        );
+
+        assert_eq!(cpu.step().unwrap(), CPUState::Running);
+        let ex = cpu.step().expect_err("Should have excepted at runtime");
+        assert_eq!(ex.kind(), CPUExceptionKind::OutOfBounds);
+        assert_eq!(cpu.pc(), usize::MAX);
+    }
+
+    #[test]
+    fn retrying_without_a_fix_gives_up() {
+        let mut cpu = IntcodeCPU::with_io(vec![42], QueueIO::default());
+        cpu.set_trap_handler(
+            CPUExceptionKind::InvalidOpcode,
+            Box::new(|_| TrapAction::Retry),
+        );
+
+        let ex = cpu.run().expect_err("Should have excepted at runtime");
+        assert_eq!(ex.kind(), CPUExceptionKind::InvalidOpcode);
+
+        cpu.clear_trap_handler(CPUExceptionKind::InvalidOpcode);
+        assert!(cpu.step().is_err());
+    }
+
+    #[test]
+    fn retrying_a_protected_input_keeps_the_input() {
+        let mut cpu = IntcodeCPU::with_io(vec![3, 0, 99], QueueIO::new(vec![5, 6]));
+        cpu.protect_range(0..1);
+        cpu.set_trap_handler(
+            CPUExceptionKind::WriteProtected,
+            Box::new(|_| TrapAction::Retry),
+        );
+
+        let ex = cpu.run().expect_err("Should have excepted at runtime");
+        assert_eq!(ex.kind(), CPUExceptionKind::WriteProtected);
+        assert_eq!(cpu.io().pending_inputs(), 2);
+        assert_eq!(cpu.inspect_state(), &[3, 0, 99]);
+    }
+}
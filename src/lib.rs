@@ -1 +1 @@
-
+pub mod intcode;
@@ -0,0 +1,132 @@
+//! Runs the `intcode` binary as a user would.
+
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn intcode(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_intcode"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Could not start intcode");
+
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn diff_reads_program_from_stdin() {
+    let output = intcode(&["diff", "-"], "1,0,0,0,99\n");
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "[0..1]\n     0: 1 -> 2\n"
+    );
+}
+
+#[test]
+fn trace_lists_instructions_and_outputs() {
+    let output = intcode(&["trace", "-"], "104,7,99");
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "0: OUTPUT 7\n        -> 7\n2: HALT\n\
+         {\"state\":\"Halted\",\"pc\":2,\"steps\":2,\"outputs\":[7]}\n"
+    );
+}
+
+#[test]
+fn overflow_is_reported_as_an_exception() {
+    let output = intcode(&["run", "-"], "1101,2147483647,1,0,99");
+
+    assert_eq!(output.status.code(), Some(2));
+    let summary = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        summary.starts_with("{\"state\":\"Exception\",\"exception\":\"Overflow: "),
+        "{}",
+        summary
+    );
+}
+
+#[test]
+fn debugger_survives_huge_addresses() {
+    let path = std::env::temp_dir().join(format!("aoc2019-debug-{}.txt", std::process::id()));
+    std::fs::write(&path, "104,7,99").unwrap();
+    let output = intcode(
+        &["debug", path.to_str().unwrap()],
+        "x 18446744073709551615 2\nx 1 5\nq\n",
+    );
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(String::from_utf8_lossy(&output.stdout).contains("  [1] 7\n  [2] 99\n"));
+}
+
+#[test]
+fn ascii_programs_converse_on_the_terminal() {
+    // Outputs each character of a line plus one, halting at the newline
+    let program = "3,20,1008,20,10,21,1005,21,19,101,1,20,20,4,20,1105,1,0,99,99,0,0";
+    let path = std::env::temp_dir().join(format!("aoc2019-ascii-{}.txt", std::process::id()));
+    std::fs::write(&path, program).unwrap();
+
+    let output = intcode(&["run", path.to_str().unwrap(), "--ascii"], "HAL\n");
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "IBM\n");
+}
+
+#[test]
+fn disasm_can_follow_a_run() {
+    let program = include_str!("../input/day05/input");
+
+    let output = intcode(&["disasm", "-"], program);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("     6  DATA 1100\n"));
+
+    let output = intcode(&["disasm", "-", "--run", "--input", "5"], program);
+    assert!(output.status.success());
+    let listing = String::from_utf8_lossy(&output.stdout);
+    assert!(listing.contains("     6  JNZ 1, 238\n"), "{}", listing);
+    assert!(listing.contains("  HALT\n"));
+}
+
+#[test]
+fn robot_fails_on_bad_output_and_honours_options() {
+    // Outputs an invalid turn, then halts
+    let output = intcode(&["robot", "-"], "3,9,104,1,104,2,99,0,0,0");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Invalid turn 2"));
+
+    // Loops forever unless --set makes the jump fall through
+    let program = "104,1,104,0,1105,1,0,99";
+    let output = intcode(&["robot", "-", "--step-limit", "100"], program);
+    assert_eq!(output.status.code(), Some(2));
+
+    let output = intcode(&["robot", "-", "--set", "5=0"], program);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "Panels painted: 1\n#\n"
+    );
+}
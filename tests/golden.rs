@@ -0,0 +1,132 @@
+//! Runs every Intcode program under `tests/intcode/`.
+//!
+//! Each case is a directory holding a `program` file and any of:
+//!
+//! * `inputs`: values fed to `Input` instructions
+//! * `outputs`: values the program must output
+//! * `memory`: the full memory image once the program has halted
+//! * `exception`: the kind of exception the program must raise instead,
+//!   e.g. `OutOfBounds`
+//!
+//! All files hold integers separated by commas and/or whitespace, with `#`
+//! comments, or a program may be a binary image. To add a
+//! regression case, create a new directory; no Rust needs to change.
+
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use aoc2019::intcode::io::QueueIO;
+use aoc2019::intcode::loader;
+use aoc2019::intcode::{CPUState, IntcodeCPU};
+
+const STEP_LIMIT: u64 = 1_000_000;
+
+fn read_values(dir: &Path, name: &str) -> Option<Vec<i32>> {
+    let path = dir.join(name);
+    if !path.exists() {
+        return None;
+    }
+
+    Some(loader::load_path(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e)))
+}
+
+/// Lists the positions at which two sequences differ, `-` for expected and
+/// `+` for actual
+fn diff(expected: &[i32], actual: &[i32]) -> String {
+    let mut out = String::new();
+
+    for i in 0..expected.len().max(actual.len()) {
+        let (exp, act) = (expected.get(i), actual.get(i));
+        if exp == act {
+            continue;
+        }
+
+        if let Some(exp) = exp {
+            writeln!(out, "    - [{}] {}", i, exp).unwrap();
+        }
+        if let Some(act) = act {
+            writeln!(out, "    + [{}] {}", i, act).unwrap();
+        }
+    }
+
+    out
+}
+
+/// Runs the case in `dir`, describing every way it failed
+fn run_case(dir: &Path) -> Result<(), String> {
+    let program = read_values(dir, "program").ok_or("missing program file")?;
+    let inputs = read_values(dir, "inputs").unwrap_or_default();
+    let exception = fs::read_to_string(dir.join("exception"))
+        .ok()
+        .map(|s| s.trim().to_string());
+
+    let mut cpu = IntcodeCPU::with_io(program, QueueIO::new(inputs));
+    cpu.set_step_limit(Some(STEP_LIMIT));
+
+    let mut problems = String::new();
+
+    match (cpu.run(), exception) {
+        (Ok(CPUState::Halted), None) => {}
+        (Ok(state), None) => writeln!(problems, "  stopped {:?} at {}", state, cpu.pc()).unwrap(),
+        (Ok(_), Some(expected)) => {
+            writeln!(problems, "  expected {} exception, halted", expected).unwrap()
+        }
+        (Err(ex), None) => writeln!(problems, "  unexpected exception {}", ex).unwrap(),
+        (Err(ex), Some(expected)) => {
+            if format!("{:?}", ex.kind()) != expected {
+                writeln!(problems, "  expected {} exception, got {}", expected, ex).unwrap();
+            }
+        }
+    }
+
+    if let Some(expected) = read_values(dir, "outputs") {
+        let actual = cpu.io().outputs();
+        if actual != &expected[..] {
+            writeln!(problems, "  outputs differ:\n{}", diff(&expected, actual)).unwrap();
+        }
+    }
+
+    if let Some(expected) = read_values(dir, "memory") {
+        let actual = cpu.inspect_state();
+        if actual != expected {
+            writeln!(problems, "  memory differs:\n{}", diff(&expected, &actual)).unwrap();
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(problems)
+    }
+}
+
+#[test]
+fn golden_intcode_programs() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/intcode");
+    let mut cases = fs::read_dir(&root)
+        .expect("tests/intcode should exist")
+        .map(|entry| entry.expect("Could not read case directory").path())
+        .filter(|path| path.is_dir())
+        .collect::<Vec<PathBuf>>();
+    cases.sort();
+
+    assert!(!cases.is_empty(), "no cases found in {}", root.display());
+
+    let mut failures = 0;
+    for case in &cases {
+        let name = case.file_name().unwrap().to_string_lossy();
+        if let Err(problems) = run_case(case) {
+            eprintln!("FAILED {}\n{}", name, problems);
+            failures += 1;
+        }
+    }
+
+    assert_eq!(
+        failures,
+        0,
+        "{} of {} golden cases failed",
+        failures,
+        cases.len()
+    );
+}